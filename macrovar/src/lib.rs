@@ -5,6 +5,7 @@ extern crate lazy_static;
 extern crate random_string;
 
 extern crate proc_macro;
+extern crate proc_macro2;
 extern crate quote;
 extern crate syn;
 
@@ -55,84 +56,294 @@ fn just_return() -> u32 {
     index_lock.get()
 }
 
-#[proc_macro_derive(ResponseCommon)]
+#[proc_macro_derive(ResponseCommon, attributes(schema))]
 pub fn derive_trait_body_type(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     let input = parse_macro_input!(input as DeriveInput);
 
-    let name = input.ident;
+    let name = &input.ident;
 
     let expanded = quote! {
-        impl samovar::common::ResponseCommon for #name {
-            fn get_length(&self) -> usize {
-                let self_str = serde_json::to_string(&self).unwrap();
+        impl samovar::common::ResponseCommon for #name {}
+    };
+
+    let mut ret = proc_macro::TokenStream::from(expanded);
+
+    ret.extend(schema_impl(&input));
+
+    ret
+}
+
+#[proc_macro_derive(Schema, attributes(schema))]
+pub fn derive_schema(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    schema_impl(&input)
+}
+
+fn schema_impl(input: &DeriveInput) -> proc_macro::TokenStream {
+    let name = &input.ident;
+    let name_str = name.to_string();
 
-                self_str.len()
-           }
+    let mut generics = input.generics.clone();
+
+    for param in generics.type_params_mut() {
+        param
+            .bounds
+            .push(syn::parse_quote!(samovar::openapi::Schema));
+    }
+
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    let type_params = input
+        .generics
+        .type_params()
+        .map(|p| &p.ident)
+        .collect::<Vec<&Ident>>();
+
+    let schema_name = quote! {
+        {
+            let mut name = #name_str.to_string();
+
+            #(name.push_str(&<#type_params as samovar::openapi::Schema>::schema_name());)*
+
+            name
+        }
+    };
 
-           fn parse_to_string(&self) -> String {
-                let self_str = serde_json::to_string(&self).unwrap();
+    let schema = match &input.data {
+        syn::Data::Struct(data) => match &data.fields {
+            syn::Fields::Named(fields) => {
+                let inserts = fields
+                    .named
+                    .iter()
+                    .filter_map(|f| field_schema(f, f.ident.as_ref().unwrap().to_string()))
+                    .collect::<Vec<_>>();
 
-                self_str
+                quote! {
+                    {
+                        let mut properties = samovar::serde_json::Map::new();
+                        let mut required: Vec<samovar::serde_json::Value> = vec![];
+
+                        #(#inserts)*
+
+                        samovar::serde_json::json!({
+                            "type": "object",
+                            "title": #name_str,
+                            "properties": properties,
+                            "required": required
+                        })
+                    }
+                }
+            }
+            syn::Fields::Unnamed(fields) if fields.unnamed.len() == 1 => {
+                field_type_schema(&fields.unnamed[0], &FieldOptions::of(&fields.unnamed[0]))
+            }
+            syn::Fields::Unnamed(fields) => {
+                let items = fields
+                    .unnamed
+                    .iter()
+                    .map(|f| field_type_schema(f, &FieldOptions::of(f)))
+                    .collect::<Vec<_>>();
+                let len = items.len();
+
+                quote! {
+                    samovar::serde_json::json!({
+                        "type": "array",
+                        "prefixItems": [#(#items),*],
+                        "minItems": #len,
+                        "maxItems": #len
+                    })
+                }
+            }
+            syn::Fields::Unit => quote! { samovar::serde_json::json!({"type": "null"}) },
+        },
+        syn::Data::Enum(data) => {
+            let unit_only = data
+                .variants
+                .iter()
+                .all(|v| matches!(v.fields, syn::Fields::Unit));
+
+            let variant_names = data
+                .variants
+                .iter()
+                .filter_map(|v| {
+                    let options = FieldOptions::from_attrs(&v.attrs);
+
+                    match options.skip {
+                        true => None,
+                        false => Some(options.rename.unwrap_or_else(|| v.ident.to_string())),
+                    }
+                })
+                .collect::<Vec<String>>();
+
+            match unit_only {
+                true => quote! {
+                    samovar::serde_json::json!({
+                        "type": "string",
+                        "title": #name_str,
+                        "enum": [#(#variant_names),*]
+                    })
+                },
+                false => quote! {
+                    samovar::serde_json::json!({
+                        "title": #name_str,
+                        "oneOf": [#({"type": "object", "required": [#variant_names]}),*]
+                    })
+                },
             }
         }
+        syn::Data::Union(_) => panic!("Schema cannot be derived for unions"),
+    };
 
+    let expanded = quote! {
+        impl #impl_generics samovar::openapi::Schema for #name #ty_generics #where_clause {
+            fn schema_name() -> String {
+                #schema_name
+            }
+
+            fn schema() -> samovar::serde_json::Value {
+                #schema
+            }
+        }
     };
 
     proc_macro::TokenStream::from(expanded)
 }
 
-#[proc_macro_attribute]
-pub fn route(args: TokenStream, input: TokenStream) -> TokenStream {
-    let args = parse_macro_input!(args as AttributeArgs);
-    let input = parse_macro_input!(input as ItemFn);
+#[derive(Default)]
+struct FieldOptions {
+    rename: Option<String>,
+    skip: bool,
+    any: bool,
+    flatten: bool,
+    optional: bool,
+}
 
-    let fname = &input.sig.ident;
+impl FieldOptions {
+    fn of(field: &syn::Field) -> Self {
+        Self::from_attrs(&field.attrs)
+    }
 
-    let first_arg = &args[0];
-    let second_arg = &args[1];
+    fn from_attrs(attrs: &[syn::Attribute]) -> Self {
+        let mut options = FieldOptions::default();
 
-    let first_arg_nv = {
-        match first_arg {
-            syn::NestedMeta::Meta(meta) => match meta {
-                syn::Meta::NameValue(nv) => nv,
-                _ => panic!("Must ba named value"),
-            },
-            _ => panic!("Must be named value"),
+        for attr in attrs.iter() {
+            let from_serde = attr.path.is_ident("serde");
+
+            if !from_serde && !attr.path.is_ident("schema") {
+                continue;
+            }
+
+            let nested = match attr.parse_meta() {
+                Ok(syn::Meta::List(list)) => list.nested,
+                _ => continue,
+            };
+
+            for meta in nested.iter() {
+                let (key, lit) = match meta {
+                    syn::NestedMeta::Meta(syn::Meta::Path(path)) => (path, None),
+                    syn::NestedMeta::Meta(syn::Meta::NameValue(nv)) => (&nv.path, Some(&nv.lit)),
+                    _ => continue,
+                };
+
+                let key = quote! {#key}.to_string();
+
+                match (key.as_str(), lit, from_serde) {
+                    ("rename", Some(syn::Lit::Str(name)), true) => {
+                        options.rename = Some(name.value())
+                    }
+                    ("skip" | "skip_serializing", _, true) | ("skip", None, false) => {
+                        options.skip = true
+                    }
+                    ("skip_serializing_if" | "default", _, true) => options.optional = true,
+                    ("flatten", None, true) => options.flatten = true,
+                    ("any", None, false) => options.any = true,
+                    (_, _, true) => {}
+                    _ => panic!("Unknown schema attribute {}", key),
+                }
+            }
         }
+
+        options
+    }
+}
+
+fn field_type_schema(field: &syn::Field, options: &FieldOptions) -> proc_macro2::TokenStream {
+    let ty = &field.ty;
+
+    match options.any {
+        true => quote! { samovar::serde_json::json!({}) },
+        false => quote! { <#ty as samovar::openapi::Schema>::schema() },
+    }
+}
+
+fn field_schema(field: &syn::Field, name: String) -> Option<proc_macro2::TokenStream> {
+    let options = FieldOptions::of(field);
+
+    if options.skip {
+        return None;
+    }
+
+    let ty = &field.ty;
+    let schema = field_type_schema(field, &options);
+
+    if options.flatten {
+        return Some(quote! {
+            let flattened = #schema;
+
+            if let Some(samovar::serde_json::Value::Object(inner)) = flattened.get("properties") {
+                properties.extend(inner.clone());
+            }
+
+            if let Some(samovar::serde_json::Value::Array(inner)) = flattened.get("required") {
+                required.extend(inner.iter().cloned());
+            }
+        });
+    }
+
+    let name = options.rename.clone().unwrap_or(name);
+
+    let is_required = match (options.optional, options.any) {
+        (true, _) => quote! { false },
+        (false, true) => quote! { true },
+        (false, false) => quote! { <#ty as samovar::openapi::Schema>::required() },
     };
 
-    let second_arg_nv = {
-        match second_arg {
-            syn::NestedMeta::Meta(meta) => match meta {
-                syn::Meta::NameValue(nv) => nv,
-                _ => panic!("Must ba named value"),
-            },
-            _ => panic!("Must be named value"),
+    Some(quote! {
+        properties.insert(#name.to_string(), #schema);
+
+        if #is_required {
+            required.push(samovar::serde_json::Value::from(#name));
         }
-    };
+    })
+}
 
-    let path_1 = &first_arg_nv.path;
-    let path_2 = &second_arg_nv.path;
+#[proc_macro_attribute]
+pub fn route(args: TokenStream, input: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(args as AttributeArgs);
+    let input = parse_macro_input!(input as ItemFn);
 
-    let f1_string = quote! {#path_1}.to_string().to_lowercase();
-    let f2_string = quote! {#path_2}.to_string().to_lowercase();
+    let fname = &input.sig.ident;
 
-    let lit_1 = &first_arg_nv.lit;
-    let lit_2 = &second_arg_nv.lit;
+    let mut lits = Vec::<(String, &syn::Lit)>::new();
 
-    let lits_map = {
-        let mut m = HashMap::<String, &syn::Lit>::new();
+    for arg in args.iter() {
+        let arg_nv = match arg {
+            syn::NestedMeta::Meta(syn::Meta::NameValue(nv)) => nv,
+            _ => panic!("Must be named value"),
+        };
 
-        m.insert(f1_string, lit_1);
-        m.insert(f2_string, lit_2);
+        let arg_path = &arg_nv.path;
 
-        m
-    };
+        lits.push((quote! {#arg_path}.to_string().to_lowercase(), &arg_nv.lit));
+    }
+
+    let get = |key: &str| lits.iter().find(|(k, _)| k == key).map(|(_, lit)| *lit);
 
-    let uri = lits_map.get("path").unwrap();
-    let method = lits_map.get("method").unwrap();
+    let uri = get("path").unwrap();
+    let method = get("method").unwrap();
 
-    let path_str = quote!(#uri).to_string().to_lowercase();
+    let path_str = quote!(#uri).to_string();
 
     let path_str_rep = path_str.replace("\"", "");
 
@@ -150,116 +361,204 @@ pub fn route(args: TokenStream, input: TokenStream) -> TokenStream {
 
     let method_str = quote!(#method).to_string().to_lowercase().replace("\"", "");
 
-    let num = format!("{}", add_and_return());
-
-    let endpint_name = format_ident!("endpoint_{}", &num);
+    let compress = match get("compress") {
+        Some(lit) => {
+            let compress_str = quote!(#lit).to_string().to_lowercase().replace("\"", "");
 
-    let function_name = format_ident!("getter_{}", &num);
-
-    let expanded = quote! {
-        #input
+            match compress_str.as_str() {
+                "true" | "on" => quote! {
+                    .with_compression(samovar::compression::CompressionConfig::new())
+                },
+                "false" | "off" => quote! { .without_compression() },
+                _ => panic!("compress must be true or false"),
+            }
+        }
+        None => quote! {},
+    };
 
-        fn #function_name() -> Box<samovar::endpoint::Endpoint> {
-            let #endpint_name = Box::new(samovar::endpoint::Endpoint::new_string_method(#path_str_rep.to_string(),  &#fname, #method_str.to_string()));
+    let host = match get("host") {
+        Some(lit) => {
+            let host_str = quote!(#lit).to_string().to_lowercase().replace("\"", "");
 
-            #endpint_name
+            quote! {
+                .with_host(samovar::router::HostPattern::parse(#host_str))
+            }
         }
-
+        None => quote! {},
     };
 
-    proc_macro::TokenStream::from(expanded)
-}
+    let name = match get("name") {
+        Some(lit) => {
+            let name_str = quote!(#lit).to_string().replace("\"", "");
 
-#[proc_macro_attribute]
-pub fn context(args: TokenStream, input: TokenStream) -> TokenStream {
-    let args = parse_macro_input!(args as AttributeArgs);
-    let input = parse_macro_input!(input as ItemFn);
+            quote! {
+                .with_name(#name_str)
+            }
+        }
+        None => quote! {},
+    };
 
-    let input_sig_inputs = input.clone().sig.inputs;
+    let mut guards = vec![];
 
-    let ty = match input_sig_inputs.first() {
-        Some(i) => match i {
-            syn::FnArg::Receiver(_) => todo!(),
-            syn::FnArg::Typed(typed) => &typed.ty,
-        },
-        None => todo!(),
-    };
+    for (key, lit) in lits.iter() {
+        let value = match lit {
+            syn::Lit::Str(lit_str) => lit_str.value(),
+            _ => quote!(#lit).to_string(),
+        };
 
-    let first_arg = &args[0];
+        let split_header = || match value.split_once(':') {
+            Some((k, v)) => (k.trim().to_string(), v.trim().to_string()),
+            None => panic!("Header guard must be in the form \"Name: value\""),
+        };
 
-    let first_arg_nv = {
-        match first_arg {
-            syn::NestedMeta::Meta(meta) => match meta {
-                syn::Meta::NameValue(nv) => nv,
-                _ => panic!("Must ba named value"),
+        let guard = match key.as_str() {
+            "content_type" => quote! {
+                samovar::guard::Guard::content_type(samovar::common::MimeType::from(#value))
             },
-            _ => panic!("Must be named value"),
-        }
-    };
+            "accepts" => quote! {
+                samovar::guard::Guard::accepts(samovar::common::MimeType::from(#value))
+            },
+            "query" => quote! { samovar::guard::Guard::query_param(#value) },
+            "header" => {
+                let (k, v) = split_header();
 
-    let path_1 = &first_arg_nv.path;
+                quote! { samovar::guard::Guard::header(#k, #v) }
+            }
+            "header_regex" => {
+                let (k, v) = split_header();
 
-    let f1_string = quote! {#path_1}.to_string().to_lowercase();
+                quote! { samovar::guard::Guard::header_regex(#k, #v) }
+            }
+            _ => continue,
+        };
 
-    let lit_1 = &first_arg_nv.lit;
+        guards.push(quote! { .with_guard(#guard) });
+    }
 
-    let lits_map = {
-        let mut m = HashMap::<String, &syn::Lit>::new();
+    let mut docs = vec![];
 
-        m.insert(f1_string, lit_1);
+    for (key, lit) in lits.iter() {
+        let value = match lit {
+            syn::Lit::Str(lit_str) => lit_str.value(),
+            _ => quote!(#lit).to_string(),
+        };
 
-        m
-    };
+        let doc = match key.as_str() {
+            "summary" => quote! { .with_summary(#value) },
+            "request" => {
+                let ty = syn::parse_str::<syn::Type>(&value).expect("request must be a type");
 
-    let ctx_name = lits_map.get("name").unwrap();
+                quote! { .with_request_schema::<#ty>() }
+            }
+            "response" => {
+                let ty = syn::parse_str::<syn::Type>(&value).expect("response must be a type");
 
-    let name_str = quote!(#ctx_name)
-        .to_string()
-        .to_lowercase()
-        .replace("\"", "");
+                quote! { .with_response_schema::<#ty>() }
+            }
+            _ => continue,
+        };
 
-    let ident_add = format_ident!("ctx_{}_insert", name_str);
-    let ident_static = format_ident!("CTX_{}", name_str.to_uppercase());
+        docs.push(doc);
+    }
 
-    let init = quote! {
-        {
-            let mut ctx = std::collections::HashMap::<String, Box<#ty>>::new();
+    let middleware = match get("middleware") {
+        Some(syn::Lit::Str(lit_str)) => lit_str
+            .value()
+            .split(';')
+            .filter(|m| !m.trim().is_empty())
+            .map(|m| {
+                let expr =
+                    syn::parse_str::<syn::Expr>(m).expect("middleware must be an expression");
 
-            std::sync::RwLock::new(ctx)
-         }
+                quote! { .with_middleware(#expr) }
+            })
+            .collect::<Vec<_>>(),
+        Some(_) => panic!("middleware must be a string"),
+        None => vec![],
     };
 
-    let init_ptr = quote_spanned! {init.span() =>
-        Box::into_raw(Box::new(#init))
+    let validator = match get("validator") {
+        Some(syn::Lit::Str(lit_str)) => {
+            let expr = syn::parse_str::<syn::Expr>(&lit_str.value())
+                .expect("validator must be an expression");
+
+            quote! { .with_validator(#expr) }
+        }
+        Some(_) => panic!("validator must be a string"),
+        None => quote! {},
     };
 
-    let expanded = quote! {
-        pub struct #ident_static;
+    let num = format!("{}", add_and_return());
 
-        impl std::ops::Deref for #ident_static {
-            type Target = std::sync::RwLock<std::collections::HashMap<String, Box<#ty>>>;
+    let endpint_name = format_ident!("endpoint_{}", &num);
 
-            fn deref(&self) -> &std::sync::RwLock<std::collections::HashMap<String, Box<#ty>>> {
-                static ONCE: std::sync::Once = std::sync::Once::new();
-                static mut VALUE: *mut std::sync::RwLock<std::collections::HashMap<String, Box<#ty>>> = 0 as *mut std::sync::RwLock<std::collections::HashMap<String, Box<#ty>>>;
+    let function_name = format_ident!("getter_{}", &num);
 
-                unsafe {
-                    ONCE.call_once(|| VALUE = #init_ptr);
-                    &*VALUE
-                }
-            }
-        }
+    let handler_name = format_ident!("handler_{}", &num);
 
-        pub fn #ident_add(key: &str, item: #ty) {
-            let mut ctx_locked = #ident_static.write().unwrap();
+    let mut extractions = vec![];
+    let mut call_args = vec![];
 
-            let item_mutex = Box::new(item);
+    for (i, arg) in input.sig.inputs.iter().enumerate() {
+        let ty = match arg {
+            syn::FnArg::Typed(typed) => &typed.ty,
+            syn::FnArg::Receiver(_) => panic!("Route handlers cannot take self"),
+        };
+
+        if let syn::Type::Reference(_) = ty.as_ref() {
+            call_args.push(quote! { request });
 
-            *ctx_locked.insert(key.to_string(), item_mutex);
+            continue;
         }
 
+        let arg_name = format_ident!("arg_{}", i);
+
+        extractions.push(quote! {
+            let #arg_name = match <#ty as samovar::extract::FromRequest>::from_request(request) {
+                Ok(value) => value,
+                Err(rejection) => return samovar::response::IntoResponse::into_response(rejection),
+            };
+        });
+
+        call_args.push(quote! { #arg_name });
+    }
+
+    let (handler, constructor) = match input.sig.asyncness {
+        Some(asyncness) => (
+            quote_spanned! {asyncness.span() =>
+                fn #handler_name(request: &samovar::request::Request) -> samovar::endpoint::BoxFuture<'_, samovar::response::Response> {
+                    Box::pin(async move {
+                        #(#extractions)*
+
+                        samovar::response::IntoResponse::into_response(#fname(#(#call_args),*).await)
+                    })
+                }
+            },
+            quote! { new_async_string_method },
+        ),
+        None => (
+            quote! {
+                fn #handler_name(request: &samovar::request::Request) -> samovar::response::Response {
+                    #(#extractions)*
+
+                    samovar::response::IntoResponse::into_response(#fname(#(#call_args),*))
+                }
+            },
+            quote! { new_string_method },
+        ),
+    };
+
+    let expanded = quote! {
         #input
 
+        #handler
+
+        fn #function_name() -> Box<samovar::endpoint::Endpoint> {
+            let #endpint_name = Box::new(samovar::endpoint::Endpoint::#constructor(#path_str_rep.to_string(), #handler_name, #method_str.to_string())#compress #host #name #(#guards)* #(#docs)* #(#middleware)* #validator);
+
+            #endpint_name
+        }
+
     };
 
     proc_macro::TokenStream::from(expanded)
@@ -456,61 +755,18 @@ pub fn static_server(args: TokenStream, input: TokenStream) -> TokenStream {
     let args = parse_macro_input!(args as AttributeArgs);
     let input = parse_macro_input!(input as ItemFn);
 
-    let first_arg = &args[0];
-    let second_arg = &args[1];
-    let third_arg = &args[2];
+    let mut lits_map = HashMap::<String, &syn::Lit>::new();
 
-    let first_arg_nv = {
-        match first_arg {
-            syn::NestedMeta::Meta(meta) => match meta {
-                syn::Meta::NameValue(nv) => nv,
-                _ => panic!("Must ba named value"),
-            },
+    for arg in args.iter() {
+        let arg_nv = match arg {
+            syn::NestedMeta::Meta(syn::Meta::NameValue(nv)) => nv,
             _ => panic!("Must be named value"),
-        }
-    };
+        };
 
-    let second_arg_nv = {
-        match second_arg {
-            syn::NestedMeta::Meta(meta) => match meta {
-                syn::Meta::NameValue(nv) => nv,
-                _ => panic!("Must ba named value"),
-            },
-            _ => panic!("Must be named value"),
-        }
-    };
+        let arg_path = &arg_nv.path;
 
-    let third_arg_nv = {
-        match third_arg {
-            syn::NestedMeta::Meta(meta) => match meta {
-                syn::Meta::NameValue(nv) => nv,
-                _ => panic!("Must ba named value"),
-            },
-            _ => panic!("Must be named value"),
-        }
-    };
-
-    let path_1 = &first_arg_nv.path;
-    let path_2 = &second_arg_nv.path;
-    let path_3 = &third_arg_nv.path;
-
-    let f1_string = quote! {#path_1}.to_string().to_lowercase();
-    let f2_string = quote! {#path_2}.to_string().to_lowercase();
-    let f3_string = quote! {#path_3}.to_string().to_lowercase();
-
-    let lit_1 = &first_arg_nv.lit;
-    let lit_2 = &second_arg_nv.lit;
-    let lit_3 = &third_arg_nv.lit;
-
-    let lits_map = {
-        let mut m = HashMap::<String, &syn::Lit>::new();
-
-        m.insert(f1_string, lit_1);
-        m.insert(f2_string, lit_2);
-        m.insert(f3_string, lit_3);
-
-        m
-    };
+        lits_map.insert(quote! {#arg_path}.to_string().to_lowercase(), &arg_nv.lit);
+    }
 
     let lit_glob = lits_map.get("glob").unwrap();
     let lit_findex = lits_map.get("index_file").unwrap();
@@ -529,9 +785,17 @@ pub fn static_server(args: TokenStream, input: TokenStream) -> TokenStream {
         .to_lowercase()
         .replace("\"", "");
 
+    let path_files_str = format!("{}/*file", path_str.trim_end_matches('/'));
+
+    let host_str = match lits_map.get("host") {
+        Some(lit) => quote!(#lit).to_string().to_lowercase().replace("\"", ""),
+        None => String::new(),
+    };
+
     let path_desensitized = path_str.replace("/", "_");
 
     let endpoint_ident = format_ident!("endpoint_{}", &path_desensitized);
+    let endpoint_files_ident = format_ident!("endpoint_files_{}", &path_desensitized);
 
     let struct_name = format_ident!("STATIC_SERVER_{}", &path_desensitized);
 
@@ -573,14 +837,19 @@ pub fn static_server(args: TokenStream, input: TokenStream) -> TokenStream {
             }
         }
 
-        #[route(method = "GET", path = #path_str)]
-        fn #endpoint_ident(r: &samovar::request::Request) -> samovar::common::ResponseTextWrapper {
+        #[route(method = "GET", path = #path_str, host = #host_str)]
+        fn #endpoint_ident(r: &samovar::request::Request) -> samovar::response::Response {
 
             let dir_server = #struct_name.read().unwrap();
 
             let req_uri = r.get_raw_uri();
 
-            dir_server.compose(req_uri)
+            dir_server.respond(req_uri)
+        }
+
+        #[route(method = "GET", path = #path_files_str, host = #host_str)]
+        fn #endpoint_files_ident(r: &samovar::request::Request) -> samovar::response::Response {
+            #endpoint_ident(r)
         }
 
         #input
@@ -645,7 +914,7 @@ pub fn samovar(args: TokenStream, input: TokenStream) -> TokenStream {
 
     let max = just_return();
 
-    for i in 1..max {
+    for i in 1..=max {
         let function_name = format_ident!("getter_{}", i);
 
         function_names.push(function_name);
@@ -653,11 +922,11 @@ pub fn samovar(args: TokenStream, input: TokenStream) -> TokenStream {
 
     let init = quote! {
         {
-            let mut sam = samovar::samovar::Samovar::new(#addr_str, #port_str.parse::<u32>().unwrap());
+            let sam = samovar::samovar::Samovar::new(#addr_str, #port_str.parse::<u32>().unwrap());
 
             #(sam.insert_endpoint(#function_names());)*
 
-            std::sync::Mutex::new(sam)
+            std::sync::RwLock::new(sam)
          }
     };
 
@@ -669,11 +938,11 @@ pub fn samovar(args: TokenStream, input: TokenStream) -> TokenStream {
         pub struct SAMOVAR;
 
         impl std::ops::Deref for SAMOVAR{
-            type Target = std::sync::Mutex<samovar::samovar::Samovar>;
+            type Target = std::sync::RwLock<samovar::samovar::Samovar>;
 
-            fn deref(&self) -> &std::sync::Mutex<samovar::samovar::Samovar> {
+            fn deref(&self) -> &std::sync::RwLock<samovar::samovar::Samovar> {
                 static ONCE: std::sync::Once = std::sync::Once::new();
-                static mut VALUE: *mut std::sync::Mutex<samovar::samovar::Samovar> = 0 as *mut std::sync::Mutex<samovar::samovar::Samovar>;
+                static mut VALUE: *mut std::sync::RwLock<samovar::samovar::Samovar> = 0 as *mut std::sync::RwLock<samovar::samovar::Samovar>;
 
                 unsafe {
                     ONCE.call_once(|| VALUE = #init_ptr);
@@ -683,172 +952,62 @@ pub fn samovar(args: TokenStream, input: TokenStream) -> TokenStream {
         }
 
 
-        pub fn add_new_endpoint(e: Box<samovar::endpoint::Endpoint>) {
-            let mut samovar_lock = SAMOVAR.lock().unwrap();
+        pub fn add_new_endpoint(e: Box<samovar::endpoint::Endpoint>) -> Result<(), String> {
+            let samovar_lock = SAMOVAR.read().unwrap();
 
-            samovar_lock.insert_endpoint(e);
+            samovar_lock.add_endpoint(*e)
         }
 
+        pub fn replace_endpoint(e: Box<samovar::endpoint::Endpoint>) -> Result<(), String> {
+            let samovar_lock = SAMOVAR.read().unwrap();
 
-        pub fn print_endpoint_uris() {
-            let mut samovar_lock = SAMOVAR.lock().unwrap();
-
-            samovar_lock.print_endpoint_uris();
-
+            samovar_lock.replace_endpoint(*e)
         }
 
-        pub fn serve_forever() {
-            let mut samovar_lock = SAMOVAR.lock().unwrap();
+        pub fn remove_endpoint(uri: &str, method: samovar::common::Method) -> bool {
+            let samovar_lock = SAMOVAR.read().unwrap();
 
-            samovar_lock.run()
+            samovar_lock.remove_endpoint(uri, method)
         }
 
-        #input
-    };
-
-    proc_macro::TokenStream::from(expanded)
-}
-
-enum Operation {
-    CREATE,
-    INSERT,
-    GET,
-    FREE,
-}
-
-#[proc_macro]
-pub fn context_global(item: TokenStream) -> TokenStream {
-    let arg = item.to_string();
-
-    let arg_split = arg.split_whitespace().collect::<Vec<&str>>();
+        pub fn remove_named_endpoint(name: &str) -> bool {
+            let samovar_lock = SAMOVAR.read().unwrap();
 
-    let operation = {
-        let first_word = &arg_split[0].to_lowercase();
-
-        match first_word.as_str() {
-            "create" => Operation::CREATE,
-            "get" => Operation::GET,
-            "insert" => Operation::INSERT,
-            "free" => Operation::FREE,
-            _ => panic!("Wrong args"),
+            samovar_lock.remove_named_endpoint(name)
         }
-    };
-
-    let expanded = match operation {
-        Operation::CREATE => {
-            let name_str = &arg_split[1];
-            let type_str = &arg_split[3];
-
-            let ident_static = format_ident!("CTX_{}", name_str.to_uppercase());
-            let ty = format_ident!("{}", type_str);
-
-            let init = quote! {
-                {
-                    let mut ctx = std::collections::HashMap::<String, Box<#ty>>::new();
-
-                    std::sync::RwLock::new(ctx)
-                 }
-            };
-
-            let init_ptr = quote_spanned! {init.span() =>
-                Box::into_raw(Box::new(#init))
-            };
-
-            let expanded = quote! {
-                pub struct #ident_static;
 
-                impl std::ops::Deref for #ident_static {
-                    type Target = std::sync::RwLock<std::collections::HashMap<String, Box<#ty>>>;
+        pub fn add_middleware<M: samovar::middleware::Middleware + 'static>(middleware: M) -> Result<(), String> {
+            let mut samovar_lock = SAMOVAR.write().unwrap();
 
-                    fn deref(&self) -> &std::sync::RwLock<std::collections::HashMap<String, Box<#ty>>> {
-                        static ONCE: std::sync::Once = std::sync::Once::new();
-                        static mut VALUE: *mut std::sync::RwLock<std::collections::HashMap<String, Box<#ty>>> = 0 as *mut std::sync::RwLock<std::collections::HashMap<String, Box<#ty>>>;
-
-                        unsafe {
-                            ONCE.call_once(|| VALUE = #init_ptr);
-                            &*VALUE
-                        }
-                    }
-                }
-
-            };
-
-            expanded
+            samovar_lock.add_middleware(middleware)
         }
-        Operation::INSERT => {
-            let value = &arg_split[1];
-            let name = &arg_split[3];
-            let key = &arg_split[5];
-
-            let value_ident = format_ident!("{}", value);
-            let ident_static = format_ident!("CTX_{}", name.to_uppercase());
-            let key_str = key.to_string();
-            let locked_name = format_ident!("lock_{}", key_str);
-
-            let expanded = quote! {
-                let mut #locked_name = #ident_static.write().unwrap();
-
-                #locked_name.insert(#key_str.to_string(), Box::new(#value_ident));
 
-                std::mem::drop(#locked_name);
-            };
+        pub fn insert_state<T: Send + Sync + 'static>(value: T) -> Result<(), String> {
+            let mut samovar_lock = SAMOVAR.write().unwrap();
 
-            expanded
+            samovar_lock.insert_state(value)
         }
-        Operation::GET => {
-            let key = &arg_split[1];
-            let name = &arg_split[3];
-
-            let unwrap = {
-                match arg_split.get(4) {
-                    Some(str) => str.to_lowercase() == "unwrap",
-                    None => false,
-                }
-            };
-
-            let ident_static = format_ident!("CTX_{}", name.to_uppercase());
-            let key_str = key.to_string();
-            let locked_name = format_ident!("lock_{}", key_str);
 
-            let item_got = format_ident!("got_{}", key);
+        pub fn queue_depth() -> usize {
+            let samovar_lock = SAMOVAR.read().unwrap();
 
-            let expanded_init = quote! {
-                let #locked_name = #ident_static.read().unwrap();
-            };
-
-            let expanded = {
-                match unwrap {
-                    true => {
-                        quote_spanned! {expanded_init.span()=>
-                            #expanded_init
+            samovar_lock.queue_depth()
+        }
 
-                            let #item_got = #locked_name.get(#key_str).unwrap();
-                        }
-                    }
-                    false => {
-                        quote_spanned! {expanded_init.span()=>
-                            #expanded_init
+        pub fn print_endpoint_uris() {
+            let samovar_lock = SAMOVAR.read().unwrap();
 
-                            let #item_got = #locked_name.get(#key_str);
-                        }
-                    }
-                }
-            };
+            samovar_lock.print_endpoint_uris();
 
-            expanded
         }
-        Operation::FREE => {
-            let key = &arg_split[1];
-
-            let key_str = key.to_string();
-            let locked_name = format_ident!("lock_{}", key_str);
 
-            let expanded = quote! {
-                std::mem::drop(#locked_name);
-            };
+        pub fn serve_forever() {
+            let server = SAMOVAR.read().unwrap().server();
 
-            expanded
+            server.run()
         }
+
+        #input
     };
 
     proc_macro::TokenStream::from(expanded)
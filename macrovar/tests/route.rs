@@ -0,0 +1,91 @@
+extern crate macrovar;
+extern crate samovar;
+
+use macrovar::route;
+use samovar::common::{HttpStatus, Params};
+use samovar::extract::{Header, Json, Path, UserAgent};
+use samovar::parser::RequestParser;
+use samovar::request::Request;
+
+#[route(path = "/users/:id", method = "GET")]
+fn user(Path(id): Path<u32>, Header(UserAgent(agent)): Header<UserAgent>) -> String {
+    format!("{} {}", id, agent)
+}
+
+#[route(path = "/sum", method = "POST")]
+fn sum(Json(numbers): Json<Vec<u32>>) -> String {
+    numbers.iter().sum::<u32>().to_string()
+}
+
+fn request(raw: &str, id: &str) -> Request {
+    let mut request = RequestParser::parse_raw(
+        raw.as_bytes(),
+        "127.0.0.1:80".parse().unwrap(),
+        samovar::parser::DEFAULT_MAX_BODY_SIZE,
+    )
+    .ok()
+    .unwrap();
+
+    request.set_path_params(vec![Params {
+        key: "id".to_string(),
+        value: id.to_string(),
+    }]);
+
+    request
+}
+
+fn served(endpoint: &samovar::endpoint::Endpoint, request: &Request) -> (HttpStatus, String) {
+    let mut response = endpoint.call(request);
+    let body = String::from_utf8(response.compose().into_bytes()).unwrap();
+
+    (response.get_status(), body)
+}
+
+#[test]
+fn extracts_handler_arguments() {
+    let raw = "GET /users/7 HTTP/1.1\r\nUser-Agent: curl/8.0\r\n\r\n";
+
+    let (status, body) = served(&getter_1(), &request(raw, "7"));
+
+    assert!(matches!(status, HttpStatus::Http200Ok));
+    assert!(body.ends_with("7 curl/8.0"));
+}
+
+#[test]
+fn answers_rejections_without_calling_the_handler() {
+    let raw = "GET /users/x HTTP/1.1\r\nUser-Agent: curl/8.0\r\n\r\n";
+
+    let (status, _) = served(&getter_1(), &request(raw, "x"));
+
+    assert!(matches!(status, HttpStatus::Http404NotFound));
+
+    let raw = "GET /users/7 HTTP/1.1\r\n\r\n";
+
+    let (status, _) = served(&getter_1(), &request(raw, "7"));
+
+    assert!(matches!(status, HttpStatus::Http400BadRequest));
+
+    let raw = "POST /sum HTTP/1.1\r\nContent-Type: text/plain\r\nContent-Length: 5\r\n\r\n[1,2]";
+
+    let (status, _) = served(&getter_2(), &request(raw, ""));
+
+    assert!(matches!(status, HttpStatus::Http415UnsupportedMediaType));
+
+    let raw =
+        "POST /sum HTTP/1.1\r\nContent-Type: application/json\r\nContent-Length: 3\r\n\r\n[1,";
+
+    let (status, _) = served(&getter_2(), &request(raw, ""));
+
+    assert!(matches!(status, HttpStatus::Http422UnprocessableEntity));
+}
+
+#[test]
+fn serves_decoded_bodies() {
+    let raw =
+        "POST /sum HTTP/1.1\r\nContent-Type: application/json\r\nContent-Length: 7\r\n\r\n[1,2,3]";
+
+    let (status, body) = served(&getter_2(), &request(raw, ""));
+
+    assert!(matches!(status, HttpStatus::Http200Ok));
+    assert!(body.ends_with("\r\n\r\n6"));
+}
@@ -0,0 +1,132 @@
+use samovar::common::Method;
+use samovar::endpoint::Endpoint;
+use samovar::request::Request;
+use samovar::samovar::{Engine, Samovar};
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const CLIENTS: usize = 8;
+const REQUESTS_PER_CLIENT: usize = 200;
+const CHUNK_SIZE: usize = 8;
+const CHUNK_DELAY: Duration = Duration::from_millis(5);
+const STARTUP_DELAY: Duration = Duration::from_millis(300);
+
+const RAW_REQUEST: &[u8] = b"GET /hello HTTP/1.1\r\nHost: localhost\r\n\r\n";
+
+fn start(engine: Engine, port: u32) {
+    thread::spawn(move || {
+        let mut samovar = Samovar::new("127.0.0.1", port);
+
+        samovar.set_engine(engine);
+
+        samovar
+            .add_endpoint(Endpoint::new(
+                "/hello".to_string(),
+                |_r: &Request| "hello",
+                Method::GET,
+            ))
+            .unwrap();
+
+        samovar.run();
+    });
+
+    thread::sleep(STARTUP_DELAY);
+}
+
+fn request(port: u32, trickle: bool) {
+    let mut stream = TcpStream::connect(("127.0.0.1", port as u16)).unwrap();
+
+    match trickle {
+        true => {
+            for chunk in RAW_REQUEST.chunks(CHUNK_SIZE) {
+                stream.write_all(chunk).unwrap();
+                thread::sleep(CHUNK_DELAY);
+            }
+        }
+        false => stream.write_all(RAW_REQUEST).unwrap(),
+    }
+
+    let mut response = vec![];
+    stream.read_to_end(&mut response).ok();
+
+    assert!(response.starts_with(b"HTTP/1.1 200"));
+}
+
+fn bench(engine: Engine, port: u32, slow_clients: usize) -> f64 {
+    let done = Arc::new(AtomicBool::new(false));
+
+    let trickling = (0..slow_clients)
+        .map(|_| {
+            let done = done.clone();
+
+            thread::spawn(move || {
+                while !done.load(Ordering::SeqCst) {
+                    request(port, true);
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let started = Instant::now();
+
+    let clients = (0..CLIENTS)
+        .map(|_| {
+            thread::spawn(move || {
+                for _ in 0..REQUESTS_PER_CLIENT {
+                    request(port, false);
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+
+    for client in clients {
+        client.join().unwrap();
+    }
+
+    let elapsed = started.elapsed();
+
+    done.store(true, Ordering::SeqCst);
+
+    for client in trickling {
+        client.join().unwrap();
+    }
+
+    println!("{:?} finished with {} slow clients", engine, slow_clients);
+
+    (CLIENTS * REQUESTS_PER_CLIENT) as f64 / elapsed.as_secs_f64()
+}
+
+fn main() {
+    let engines = [(Engine::Threaded, 18081), (Engine::EventLoop, 18082)];
+
+    for (engine, port) in engines.iter() {
+        start(*engine, *port);
+    }
+
+    let mut results = vec![];
+
+    for slow_clients in [0, 2] {
+        for (engine, port) in engines.iter() {
+            results.push((*engine, slow_clients, bench(*engine, *port, slow_clients)));
+        }
+    }
+
+    println!();
+    println!(
+        "{:<12}{:>14}{:>16}",
+        "engine", "slow clients", "requests/sec"
+    );
+
+    for (engine, slow_clients, throughput) in results {
+        println!(
+            "{:<12}{:>14}{:>16.0}",
+            format!("{:?}", engine),
+            slow_clients,
+            throughput
+        );
+    }
+}
@@ -0,0 +1,189 @@
+use crate::common::{HttpStatus, ResponseTextWrapper};
+use crate::endpoint::Endpoint;
+use crate::parser::{IncrementalParser, ParseError};
+use crate::pool::PoolStats;
+use crate::request::Request;
+use crate::response::IntoResponse;
+use crate::samovar::{Dispatch, Dispatcher};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::Semaphore;
+
+struct Queued<'a>(&'a PoolStats);
+
+impl Drop for Queued<'_> {
+    fn drop(&mut self) {
+        self.0.dequeue();
+    }
+}
+
+struct InFlight<'a>(&'a PoolStats);
+
+impl<'a> InFlight<'a> {
+    fn begin(stats: &'a PoolStats) -> Self {
+        stats.begin();
+
+        InFlight(stats)
+    }
+}
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        self.0.end();
+    }
+}
+
+pub async fn serve(bind: String, dispatcher: Dispatcher, stats: Arc<PoolStats>) {
+    let settings = dispatcher.get_settings();
+
+    let permits = Arc::new(Semaphore::new(settings.pool.get_workers()));
+
+    println!("Starting async server on: {}", bind);
+
+    let listener = TcpListener::bind(bind).await.unwrap();
+
+    loop {
+        let (stream, ip) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                println!("Could not accept connection: {}", e);
+                continue;
+            }
+        };
+
+        let dispatcher = dispatcher.clone();
+        let permits = permits.clone();
+        let stats = stats.clone();
+
+        tokio::spawn(async move {
+            serve_connection(stream, ip, dispatcher, permits, stats).await;
+        });
+    }
+}
+
+async fn read_request(
+    stream: &mut TcpStream,
+    ip: SocketAddr,
+    max_body_size: usize,
+    read_timeout: Duration,
+) -> Result<Request, ParseError> {
+    let mut parser = IncrementalParser::new(max_body_size);
+
+    let read = async {
+        let mut chunk = [0u8; 8192];
+
+        loop {
+            let read = stream
+                .read(&mut chunk)
+                .await
+                .map_err(|e| ParseError::Malformed(e.to_string()))?;
+
+            if read == 0 {
+                return match parser.is_empty() {
+                    true => Err(ParseError::Malformed("empty request".to_string())),
+                    false => Err(ParseError::Malformed("incomplete request".to_string())),
+                };
+            }
+
+            if parser.feed(&chunk[..read])? {
+                return Ok(());
+            }
+        }
+    };
+
+    match tokio::time::timeout(read_timeout, read).await {
+        Ok(read) => read?,
+        Err(_) => return Err(ParseError::Timeout),
+    }
+
+    parser.into_request(ip)
+}
+
+async fn write_response(stream: &mut TcpStream, wrapper: ResponseTextWrapper) {
+    if let Err(e) = stream.write_all(wrapper.as_bytes()).await {
+        println!("Could not write response: {}", e);
+    }
+
+    stream.shutdown().await.ok();
+}
+
+async fn serve_connection(
+    mut stream: TcpStream,
+    ip: SocketAddr,
+    dispatcher: Dispatcher,
+    permits: Arc<Semaphore>,
+    stats: Arc<PoolStats>,
+) {
+    let settings = dispatcher.get_settings();
+
+    let request = read_request(
+        &mut stream,
+        ip,
+        settings.max_body_size,
+        settings.read_timeout,
+    )
+    .await;
+
+    let request = match request {
+        Ok(request) => request,
+        Err(e) => {
+            println!("Request could not be parsed");
+            write_response(&mut stream, e.into_response().compose()).await;
+            return;
+        }
+    };
+
+    let (endpoint, mut request) = match dispatcher.dispatch(request) {
+        Dispatch::Endpoint(endpoint, request) => (endpoint, *request),
+        Dispatch::Respond(mut resp) => {
+            write_response(&mut stream, resp.compose()).await;
+            return;
+        }
+    };
+
+    if !stats.try_enqueue(&settings.pool) {
+        println!("Handler capacity is exhausted, shedding request");
+
+        let retry_after = settings.pool.get_retry_after();
+        let mut resp = Endpoint::service_unavailable(retry_after);
+
+        write_response(&mut stream, resp.compose()).await;
+        return;
+    }
+
+    let queued = Queued(&stats);
+
+    let _permit = match permits.acquire().await {
+        Ok(permit) => permit,
+        Err(_) => return,
+    };
+
+    let in_flight = InFlight::begin(&stats);
+
+    drop(queued);
+
+    let handled = match endpoint.is_async() {
+        true => {
+            tokio::spawn(async move { endpoint.respond_async(request, settings).await.compose() })
+                .await
+        }
+        false => {
+            tokio::task::spawn_blocking(move || endpoint.respond(&mut request, &settings).compose())
+                .await
+        }
+    };
+
+    drop(in_flight);
+
+    let wrapper = match handled {
+        Ok(wrapper) => wrapper,
+        Err(_) => HttpStatus::Http500InternalServerError
+            .into_response()
+            .compose(),
+    };
+
+    write_response(&mut stream, wrapper).await;
+}
@@ -1,7 +1,7 @@
 use core::panic;
-use std::{marker::PhantomData, net::{TcpStream, Shutdown}, io::Write, sync::Mutex};
-use serde::Deserialize;
-use serde_json::{from_str, Value};
+use std::{net::{TcpStream, Shutdown}, io::{Read, Seek, SeekFrom, Write}, sync::{Arc, Mutex}};
+use serde::Serialize;
+use serde_json::{from_slice, to_string, to_vec, Value};
 
 #[derive(Clone)]
 pub struct Header {
@@ -16,6 +16,13 @@ pub enum MimeType {
     ApplicationOctetStream,
     TextJavaScript,
     TextCSS,
+    ImagePng,
+    ImageJpeg,
+    ImageGif,
+    ApplicationPdf,
+    ApplicationProtobuf,
+    TextCsv,
+    Other(String),
 }
 
 impl Into<String> for MimeType {
@@ -27,14 +34,44 @@ impl Into<String> for MimeType {
             MimeType::ApplicationOctetStream => "application/octet-stream".to_string(),
             MimeType::TextJavaScript => "text/javascript".to_string(),
             MimeType::TextCSS => "text/css".to_string(),
+            MimeType::ImagePng => "image/png".to_string(),
+            MimeType::ImageJpeg => "image/jpeg".to_string(),
+            MimeType::ImageGif => "image/gif".to_string(),
+            MimeType::ApplicationPdf => "application/pdf".to_string(),
+            MimeType::ApplicationProtobuf => "application/x-protobuf".to_string(),
+            MimeType::TextCsv => "text/csv".to_string(),
+            MimeType::Other(t) => t,
         }
     } 
 }
 
+impl From<&str> for MimeType {
+    fn from(s: &str) -> Self {
+        let essence = s.split(';').next().unwrap_or("").trim().to_lowercase();
+
+        match essence.as_str() {
+            "application/json" => MimeType::ApplicationJson,
+            "text/plain" => MimeType::TextPlain,
+            "text/html" => MimeType::TextHtml,
+            "application/octet-stream" => MimeType::ApplicationOctetStream,
+            "text/javascript" => MimeType::TextJavaScript,
+            "text/css" => MimeType::TextCSS,
+            "image/png" => MimeType::ImagePng,
+            "image/jpeg" => MimeType::ImageJpeg,
+            "image/gif" => MimeType::ImageGif,
+            "application/pdf" => MimeType::ApplicationPdf,
+            "application/x-protobuf" => MimeType::ApplicationProtobuf,
+            "text/csv" => MimeType::TextCsv,
+            _ => MimeType::Other(essence),
+        }
+    }
+}
+
 #[derive(Clone)]
 pub enum RequestBodyType {
     Json(Value),
     Str(String),
+    Bytes(Vec<u8>),
 }
 
 #[derive(Clone)]
@@ -44,50 +81,118 @@ pub struct RequestBody {
 }
 
 impl RequestBody {
-    pub fn from_str(t: String, ctype: self::MimeType) -> Self {
-        match ctype {
-            MimeType::ApplicationJson => {
-                let j: Value = from_str(t.as_str()).unwrap();
+    pub fn from_bytes(b: Vec<u8>, ctype: self::MimeType) -> Self {
+        if b.is_empty() {
+            return RequestBody{content: RequestBodyType::Str(String::new()), content_type: ctype};
+        }
 
-                RequestBody{content: RequestBodyType::Json(j), content_type: ctype}
-            },
-            _ => RequestBody{content: RequestBodyType::Str(t), content_type: ctype},
+        if ctype == MimeType::ApplicationJson {
+            if let Ok(j) = from_slice::<Value>(&b) {
+                return RequestBody{content: RequestBodyType::Json(j), content_type: ctype};
+            }
+        }
+
+        match String::from_utf8(b) {
+            Ok(t) => RequestBody{content: RequestBodyType::Str(t), content_type: ctype},
+            Err(e) => RequestBody{content: RequestBodyType::Bytes(e.into_bytes()), content_type: ctype},
         }
     }
 
 }
 
 #[derive(Clone)]
-pub enum ResponseBodyType<'a, T: Clone + ResponseCommon + Deserialize<'a>> {
-    Object(T),
+pub enum ResponseBodyType {
     Str(String),
-    PhantomData(&'a T),
+    Bytes(Vec<u8>),
+    Seekable(Arc<Mutex<Box<dyn ReadSeek>>>, u64),
 }
 
-pub trait ResponseCommon {
-    fn parse_to_string(&self) -> String;
-    fn get_length(&self) -> usize;
+pub trait ReadSeek: Read + Seek + Send {}
+
+impl<T: Read + Seek + Send> ReadSeek for T {}
+
+pub trait ResponseCommon: Serialize {
+    fn parse_to_string(&self) -> String {
+        to_string(self).unwrap()
+    }
+
+    fn get_length(&self) -> usize {
+        self.parse_to_string().len()
+    }
 }
 
-pub struct ResponseBody<'a, T: Clone + ResponseCommon + Deserialize<'a>> {
+#[derive(Clone)]
+pub struct ResponseBody {
     pub content_type: MimeType,
-    pub content: ResponseBodyType<'a, T>,
-    _phantom: PhantomData<&'a T>
+    pub content: ResponseBodyType,
 }
 
-impl<'a,T: Clone + ResponseCommon + Deserialize<'a>> ResponseBody<'a, T> {
-    pub fn new_json(content: T) -> Self {       
-        ResponseBody{content: ResponseBodyType::Object(content), 
-            content_type: MimeType::ApplicationJson, 
-            _phantom: PhantomData}            
+impl ResponseBody {
+    pub fn new_json<T: Serialize>(content: T) -> Self {
+        ResponseBody{content: ResponseBodyType::Bytes(to_vec(&content).unwrap()),
+            content_type: MimeType::ApplicationJson}
     }
 
     pub fn new_string(ctype: MimeType, t: String) -> Self {
         match ctype {
             MimeType::ApplicationJson => panic!("Wrong mimetype"),
-            _ => ResponseBody {content_type: ctype, 
-                                content: ResponseBodyType::Str(t),
-                                _phantom: PhantomData},
+            _ => ResponseBody {content_type: ctype,
+                                content: ResponseBodyType::Str(t)},
+        }
+    }
+
+    pub fn new_bytes(ctype: MimeType, b: Vec<u8>) -> Self {
+        ResponseBody {content_type: ctype,
+                        content: ResponseBodyType::Bytes(b)}
+    }
+
+    pub fn new_seekable(ctype: MimeType, mut reader: Box<dyn ReadSeek>) -> std::io::Result<Self> {
+        let length = reader.seek(SeekFrom::End(0))?;
+
+        Ok(ResponseBody {content_type: ctype,
+                        content: ResponseBodyType::Seekable(Arc::new(Mutex::new(reader)), length)})
+    }
+
+    pub fn len(&self) -> usize {
+        match &self.content {
+            ResponseBodyType::Str(t) => t.len(),
+            ResponseBodyType::Bytes(b) => b.len(),
+            ResponseBodyType::Seekable(_, length) => *length as usize,
+        }
+    }
+
+    pub fn is_seekable(&self) -> bool {
+        matches!(self.content, ResponseBodyType::Seekable(_, _))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn to_bytes(&self) -> std::io::Result<Vec<u8>> {
+        match &self.content {
+            ResponseBodyType::Str(t) => Ok(t.clone().into_bytes()),
+            ResponseBodyType::Bytes(b) => Ok(b.clone()),
+            ResponseBodyType::Seekable(_, length) => self.read_range(0, *length),
+        }
+    }
+
+    pub fn read_range(&self, start: u64, end: u64) -> std::io::Result<Vec<u8>> {
+        match &self.content {
+            ResponseBodyType::Str(t) => Ok(t.as_bytes()[start as usize..end as usize].to_vec()),
+            ResponseBodyType::Bytes(b) => Ok(b[start as usize..end as usize].to_vec()),
+            ResponseBodyType::Seekable(reader, _) => {
+                let mut reader_lock = reader.lock().unwrap_or_else(|e| e.into_inner());
+                let mut ret = vec![];
+
+                reader_lock.seek(SeekFrom::Start(start))?;
+                reader_lock.by_ref().take(end - start).read_to_end(&mut ret)?;
+
+                match ret.len() as u64 == end - start {
+                    true => Ok(ret),
+                    false => Err(std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "body is shorter than its length")),
+                }
+            }
         }
     }
 }
@@ -98,9 +203,10 @@ pub struct Params {
     pub value: String,
 }
 
-#[derive(Clone, PartialEq, Eq)]
+#[derive(Clone, PartialEq, Eq, Hash)]
 pub enum Method {
     GET,
+    HEAD,
     POST,
     OPTION,
     PUT,
@@ -112,6 +218,7 @@ impl From<String> for Method {
     fn from(s: String) -> Self {
         match s.to_lowercase().as_str() {
             "get" => Method::GET,
+            "head" => Method::HEAD,
             "post" => Method::POST,
             "option" => Method::OPTION,
             "put" => Method::PUT,
@@ -121,10 +228,17 @@ impl From<String> for Method {
     }
 }
 
+impl Method {
+    pub fn is_safe(&self) -> bool {
+        matches!(self, Method::GET | Method::HEAD | Method::OPTION)
+    }
+}
+
 impl Into<String> for Method {
     fn into(self) -> String {
         match self {
             Method::GET => "GET".to_string(),
+            Method::HEAD => "HEAD".to_string(),
             Method::POST => "POST".to_string(),
             Method::OPTION => "OPTION".to_string(),
             Method::PUT => "PUT".to_string(),
@@ -282,6 +396,18 @@ impl From<u32> for HttpStatus {
 }
 
 
+impl HttpStatus {
+    pub fn get_code(&self) -> u32 {
+        let status_string: String = (*self).into();
+
+        status_string[..3].parse::<u32>().unwrap()
+    }
+
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.get_code())
+    }
+}
+
 impl Into<String> for HttpStatus {
     fn into(self) -> String {
         match self {
@@ -352,34 +478,37 @@ impl Into<String> for HttpStatus {
     }
 }
 
-#[derive(Deserialize, Clone)]
-pub struct DummyResponseType;
+pub struct ResponseTextWrapper(Vec<u8>);
 
-impl ResponseCommon for DummyResponseType {
-    fn parse_to_string(&self) -> String {
-        String::new()
+impl ResponseTextWrapper {
+    pub fn new(t: String) -> Self {
+        ResponseTextWrapper(t.into_bytes())
     }
 
-    fn get_length(&self) -> usize {
-        0usize
+    pub fn new_bytes(b: Vec<u8>) -> Self {
+        ResponseTextWrapper(b)
     }
-}
 
+    pub fn as_bytes(&self) -> &[u8] {
+        let ResponseTextWrapper(b) = self;
 
+        b
+    }
 
-pub struct ResponseTextWrapper(String);
+    pub fn into_bytes(self) -> Vec<u8> {
+        let ResponseTextWrapper(b) = self;
 
-impl ResponseTextWrapper {
-    pub fn new(t: String) -> Self {
-        ResponseTextWrapper(t)
+        b
     }
 
     pub fn serve(&self, stream: &mut TcpStream) {
-        let ResponseTextWrapper(t) = self;
+        let ResponseTextWrapper(b) = self;
 
-        stream.write_all(t.as_bytes()).unwrap();
+        if let Err(e) = stream.write_all(b) {
+            println!("Could not write response: {}", e);
+        }
 
-        stream.shutdown(Shutdown::Both).unwrap();
+        stream.shutdown(Shutdown::Both).ok();
 
         println!("Served!");
     }
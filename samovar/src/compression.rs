@@ -0,0 +1,328 @@
+use crate::common::MimeType;
+use crate::negotiation::parse_quality_list;
+use flate2::read::{DeflateDecoder, MultiGzDecoder, ZlibDecoder};
+use flate2::write::{GzEncoder, ZlibEncoder};
+use flate2::Compression;
+use std::io::{Read, Write};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Encoding {
+    Gzip,
+    Deflate,
+    Brotli,
+    Identity,
+}
+
+impl Encoding {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "gzip" | "x-gzip" => Some(Encoding::Gzip),
+            "deflate" => Some(Encoding::Deflate),
+            "br" => Some(Encoding::Brotli),
+            "identity" => Some(Encoding::Identity),
+            _ => None,
+        }
+    }
+}
+
+impl From<Encoding> for String {
+    fn from(e: Encoding) -> Self {
+        match e {
+            Encoding::Gzip => "gzip".to_string(),
+            Encoding::Deflate => "deflate".to_string(),
+            Encoding::Brotli => "br".to_string(),
+            Encoding::Identity => "identity".to_string(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct CompressionConfig {
+    encodings: Vec<Encoding>,
+    min_size: usize,
+    skip: Vec<MimeType>,
+    level: u32,
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        let skip = vec![
+            MimeType::ImagePng,
+            MimeType::ImageJpeg,
+            MimeType::ImageGif,
+            MimeType::ApplicationPdf,
+            MimeType::Other("application/zip".to_string()),
+            MimeType::Other("application/gzip".to_string()),
+            MimeType::Other("image/webp".to_string()),
+            MimeType::Other("video/mp4".to_string()),
+            MimeType::Other("video/webm".to_string()),
+            MimeType::Other("audio/mpeg".to_string()),
+            MimeType::Other("font/woff2".to_string()),
+        ];
+
+        CompressionConfig {
+            encodings: vec![Encoding::Brotli, Encoding::Gzip, Encoding::Deflate],
+            min_size: 1024,
+            skip,
+            level: 6,
+        }
+    }
+}
+
+impl CompressionConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn encodings(mut self, encodings: Vec<Encoding>) -> Self {
+        self.encodings = encodings;
+
+        self
+    }
+
+    pub fn min_size(mut self, min_size: usize) -> Self {
+        self.min_size = min_size;
+
+        self
+    }
+
+    pub fn skip(mut self, mime: MimeType) -> Self {
+        self.skip.push(mime);
+
+        self
+    }
+
+    pub fn level(mut self, level: u32) -> Self {
+        self.level = level;
+
+        self
+    }
+
+    pub fn get_min_size(&self) -> usize {
+        self.min_size
+    }
+
+    pub fn get_level(&self) -> u32 {
+        self.level
+    }
+
+    pub fn is_skipped(&self, mime: &MimeType) -> bool {
+        self.skip.contains(mime)
+    }
+
+    pub fn negotiate(&self, accept_encoding: Option<String>) -> Encoding {
+        negotiate_encoding(accept_encoding, &self.encodings)
+    }
+}
+
+#[derive(Clone)]
+pub enum CompressionPolicy {
+    Inherit,
+    Disabled,
+    Enabled(CompressionConfig),
+}
+
+pub fn negotiate_encoding(accept_encoding: Option<String>, available: &[Encoding]) -> Encoding {
+    let items = match accept_encoding {
+        Some(header) => parse_quality_list(&header),
+        None => return Encoding::Identity,
+    };
+
+    let wildcard = items.iter().find(|i| i.value == "*").map(|i| i.q);
+
+    let mut best: Option<(Encoding, f32)> = None;
+
+    for encoding in available {
+        let q = items
+            .iter()
+            .find(|i| Encoding::parse(&i.value) == Some(*encoding))
+            .map(|i| i.q)
+            .or(wildcard)
+            .unwrap_or(0.0);
+
+        if q <= 0.0 {
+            continue;
+        }
+
+        match best {
+            Some((_, best_q)) if best_q >= q => {}
+            _ => best = Some((*encoding, q)),
+        }
+    }
+
+    match best {
+        Some((encoding, _)) => encoding,
+        None => Encoding::Identity,
+    }
+}
+
+pub fn compress(data: &[u8], encoding: Encoding, level: u32) -> Vec<u8> {
+    match encoding {
+        Encoding::Gzip => {
+            let mut encoder = GzEncoder::new(vec![], Compression::new(level.min(9)));
+            encoder.write_all(data).unwrap();
+            encoder.finish().unwrap()
+        }
+        Encoding::Deflate => {
+            let mut encoder = ZlibEncoder::new(vec![], Compression::new(level.min(9)));
+            encoder.write_all(data).unwrap();
+            encoder.finish().unwrap()
+        }
+        Encoding::Brotli => {
+            let mut ret = vec![];
+            {
+                let mut encoder = brotli::CompressorWriter::new(&mut ret, 4096, level.min(11), 22);
+                encoder.write_all(data).unwrap();
+            }
+            ret
+        }
+        Encoding::Identity => data.to_vec(),
+    }
+}
+
+pub enum DecompressError {
+    TooLarge,
+    Invalid(String),
+}
+
+fn read_limited<R: Read>(reader: R, limit: usize) -> Result<Vec<u8>, DecompressError> {
+    let mut ret = vec![];
+
+    reader
+        .take(limit as u64 + 1)
+        .read_to_end(&mut ret)
+        .map_err(|e| DecompressError::Invalid(e.to_string()))?;
+
+    match ret.len() > limit {
+        true => Err(DecompressError::TooLarge),
+        false => Ok(ret),
+    }
+}
+
+pub fn decompress(
+    data: &[u8],
+    encoding: Encoding,
+    limit: usize,
+) -> Result<Vec<u8>, DecompressError> {
+    match encoding {
+        Encoding::Gzip => read_limited(MultiGzDecoder::new(data), limit),
+        Encoding::Deflate => match read_limited(ZlibDecoder::new(data), limit) {
+            Err(DecompressError::Invalid(_)) => read_limited(DeflateDecoder::new(data), limit),
+            other => other,
+        },
+        Encoding::Brotli => read_limited(brotli::Decompressor::new(data, 4096), limit),
+        Encoding::Identity => read_limited(data, limit),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::HttpStatus;
+    use crate::conditional::ETag;
+    use crate::parser::test_request;
+    use crate::request::Request;
+    use crate::response::Response;
+
+    const AVAILABLE: [Encoding; 3] = [Encoding::Brotli, Encoding::Gzip, Encoding::Deflate];
+
+    fn request(accept_encoding: &str) -> Request {
+        let header = format!("Accept-Encoding: {}", accept_encoding);
+
+        test_request("GET", "/doc", &["Host: localhost", &header], "")
+    }
+
+    fn negotiated(accept_encoding: &str) -> Encoding {
+        negotiate_encoding(Some(accept_encoding.to_string()), &AVAILABLE)
+    }
+
+    fn text(len: usize, mime: MimeType) -> Response {
+        Response::new_string("a".repeat(len), mime, HttpStatus::Http200Ok)
+    }
+
+    #[test]
+    fn selects_the_best_accepted_encoding() {
+        assert_eq!(negotiate_encoding(None, &AVAILABLE), Encoding::Identity);
+        assert_eq!(negotiated("gzip, deflate"), Encoding::Gzip);
+        assert_eq!(negotiated("deflate, gzip, br"), Encoding::Brotli);
+        assert_eq!(negotiated("br;q=0.5, GZIP"), Encoding::Gzip);
+        assert_eq!(negotiated("x-gzip;q=0.2, deflate;q=0.8"), Encoding::Deflate);
+        assert_eq!(negotiated("*"), Encoding::Brotli);
+        assert_eq!(negotiated("compress, zstd"), Encoding::Identity);
+    }
+
+    #[test]
+    fn q_zero_excludes_an_encoding() {
+        assert_eq!(negotiated("gzip;q=0"), Encoding::Identity);
+        assert_eq!(negotiated("*;q=0.3, br;q=0"), Encoding::Gzip);
+        assert_eq!(negotiated("*;q=0"), Encoding::Identity);
+    }
+
+    #[test]
+    fn round_trips_every_encoding() {
+        let data = b"samovar ".repeat(64);
+
+        for encoding in AVAILABLE.into_iter().chain([Encoding::Identity]) {
+            let compressed = compress(&data, encoding, 6);
+
+            match decompress(&compressed, encoding, data.len()) {
+                Ok(decompressed) => assert_eq!(decompressed, data),
+                Err(_) => panic!("{:?} did not round trip", encoding),
+            }
+
+            assert!(matches!(
+                decompress(&compressed, encoding, data.len() - 1),
+                Err(DecompressError::TooLarge)
+            ));
+        }
+
+        assert!(matches!(
+            decompress(b"not gzip", Encoding::Gzip, 1024),
+            Err(DecompressError::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn compresses_responses_above_the_threshold() {
+        let config = CompressionConfig::new().min_size(16);
+
+        let mut resp = text(64, MimeType::TextPlain);
+        resp.set_etag(ETag::strong("v1"));
+        resp.compress(&request("gzip"), &config);
+
+        assert_eq!(resp.get_header("Content-Encoding").as_deref(), Some("gzip"));
+        assert_eq!(resp.get_header("Vary").as_deref(), Some("Accept-Encoding"));
+        assert!(resp.get_etag().unwrap().is_weak());
+
+        let mut resp = text(15, MimeType::TextPlain);
+        resp.compress(&request("gzip"), &config);
+
+        assert!(resp.get_header("Content-Encoding").is_none());
+        assert!(resp.get_header("Vary").is_none());
+
+        let mut resp = text(64, MimeType::TextPlain);
+        resp.compress(&request("identity"), &config);
+
+        assert!(resp.get_header("Content-Encoding").is_none());
+        assert_eq!(resp.get_header("Vary").as_deref(), Some("Accept-Encoding"));
+    }
+
+    #[test]
+    fn skips_compressed_types_and_encoded_bodies() {
+        let config = CompressionConfig::new().min_size(0).skip(MimeType::TextCsv);
+
+        for mime in [MimeType::ImagePng, MimeType::TextCsv] {
+            let mut resp = text(64, mime);
+            resp.compress(&request("gzip"), &config);
+
+            assert!(resp.get_header("Content-Encoding").is_none());
+        }
+
+        let mut resp = text(64, MimeType::TextPlain);
+        resp.set_header("Content-Encoding".to_string(), "br".to_string());
+        resp.compress(&request("gzip"), &config);
+
+        assert_eq!(resp.get_header("Content-Encoding").as_deref(), Some("br"));
+        assert!(resp.get_header("Vary").is_none());
+    }
+}
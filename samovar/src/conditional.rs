@@ -0,0 +1,335 @@
+use crate::common::{HttpStatus, Method};
+use crate::request::Request;
+use chrono::{DateTime, Utc};
+use std::sync::Arc;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum ETagKind {
+    Strong,
+    Weak,
+}
+
+#[derive(Clone, PartialEq, Eq)]
+pub struct ETag {
+    tag: String,
+    weak: bool,
+}
+
+impl ETag {
+    pub fn strong(tag: &str) -> Self {
+        ETag {
+            tag: tag.to_string(),
+            weak: false,
+        }
+    }
+
+    pub fn weak(tag: &str) -> Self {
+        ETag {
+            tag: tag.to_string(),
+            weak: true,
+        }
+    }
+
+    pub fn from_bytes(b: &[u8], kind: ETagKind) -> Self {
+        let mut hash = 0xcbf29ce484222325u64;
+
+        for byte in b {
+            hash ^= *byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+
+        let tag = format!("{:x}-{:016x}", b.len(), hash);
+
+        match kind {
+            ETagKind::Strong => ETag::strong(&tag),
+            ETagKind::Weak => ETag::weak(&tag),
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        let s = s.trim();
+
+        let (weak, quoted) = match s.strip_prefix("W/") {
+            Some(rest) => (true, rest),
+            None => (false, s),
+        };
+
+        let tag = quoted.strip_prefix('"')?.strip_suffix('"')?;
+
+        Some(ETag {
+            tag: tag.to_string(),
+            weak,
+        })
+    }
+
+    pub fn parse_list(s: &str) -> Vec<Self> {
+        s.split(',').filter_map(Self::parse).collect()
+    }
+
+    pub fn is_weak(&self) -> bool {
+        self.weak
+    }
+
+    pub fn to_weak(&self) -> Self {
+        ETag::weak(&self.tag)
+    }
+
+    pub fn strong_eq(&self, other: &ETag) -> bool {
+        !self.weak && !other.weak && self.tag == other.tag
+    }
+
+    pub fn weak_eq(&self, other: &ETag) -> bool {
+        self.tag == other.tag
+    }
+}
+
+impl From<ETag> for String {
+    fn from(e: ETag) -> Self {
+        match e.weak {
+            true => format!("W/\"{}\"", e.tag),
+            false => format!("\"{}\"", e.tag),
+        }
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct Validators {
+    etag: Option<ETag>,
+    last_modified: Option<DateTime<Utc>>,
+}
+
+impl Validators {
+    pub fn new() -> Self {
+        Validators::default()
+    }
+
+    pub fn with_etag(mut self, etag: ETag) -> Self {
+        self.etag = Some(etag);
+
+        self
+    }
+
+    pub fn with_last_modified(mut self, datetime: DateTime<Utc>) -> Self {
+        self.last_modified = Some(datetime);
+
+        self
+    }
+
+    pub fn get_etag(&self) -> Option<ETag> {
+        self.etag.clone()
+    }
+
+    pub fn get_last_modified(&self) -> Option<DateTime<Utc>> {
+        self.last_modified
+    }
+}
+
+pub type Validator = Arc<dyn Fn(&Request) -> Option<Validators> + Send + Sync>;
+
+pub fn format_http_date(datetime: &DateTime<Utc>) -> String {
+    datetime.format("%a, %d %b %Y %H:%M:%S GMT").to_string()
+}
+
+pub fn parse_http_date(s: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc2822(s.trim())
+        .ok()
+        .map(|d| d.with_timezone(&Utc))
+}
+
+fn matches_any(header: &str, etag: Option<&ETag>, strong: bool) -> bool {
+    if header.trim() == "*" {
+        return true;
+    }
+
+    match etag {
+        Some(etag) => ETag::parse_list(header)
+            .iter()
+            .any(|candidate| match strong {
+                true => candidate.strong_eq(etag),
+                false => candidate.weak_eq(etag),
+            }),
+        None => false,
+    }
+}
+
+pub fn evaluate(
+    request: &Request,
+    etag: Option<&ETag>,
+    last_modified: Option<DateTime<Utc>>,
+) -> Option<HttpStatus> {
+    let is_read = matches!(request.get_method(), Method::GET | Method::HEAD);
+    let last_modified_secs = last_modified.map(|d| d.timestamp());
+
+    match request.get_header("if-match") {
+        Some(header) => {
+            if !matches_any(&header, etag, true) {
+                return Some(HttpStatus::Http412PreconditionFailed);
+            }
+        }
+        None => {
+            let since = request
+                .get_header("if-unmodified-since")
+                .and_then(|h| parse_http_date(&h));
+
+            if let (Some(since), Some(modified)) = (since, last_modified_secs) {
+                if modified > since.timestamp() {
+                    return Some(HttpStatus::Http412PreconditionFailed);
+                }
+            }
+        }
+    }
+
+    match request.get_header("if-none-match") {
+        Some(header) => {
+            if matches_any(&header, etag, false) {
+                return match is_read {
+                    true => Some(HttpStatus::Http304NotModified),
+                    false => Some(HttpStatus::Http412PreconditionFailed),
+                };
+            }
+        }
+        None => {
+            let since = request
+                .get_header("if-modified-since")
+                .and_then(|h| parse_http_date(&h));
+
+            if let (true, Some(since), Some(modified)) = (is_read, since, last_modified_secs) {
+                if modified <= since.timestamp() {
+                    return Some(HttpStatus::Http304NotModified);
+                }
+            }
+        }
+    }
+
+    None
+}
+
+pub fn evaluate_current(request: &Request, current: Option<&Validators>) -> Option<HttpStatus> {
+    match current {
+        Some(current) => evaluate(request, current.etag.as_ref(), current.last_modified),
+        None if request.get_header("if-match").is_some() => {
+            Some(HttpStatus::Http412PreconditionFailed)
+        }
+        None => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::test_request;
+
+    fn request(method: &str, headers: &[&str]) -> Request {
+        test_request(
+            method,
+            "/doc",
+            &[&["Host: localhost"], headers].concat(),
+            "",
+        )
+    }
+
+    fn date(s: &str) -> DateTime<Utc> {
+        parse_http_date(s).unwrap()
+    }
+
+    #[test]
+    fn parses_strong_and_weak_etags() {
+        let tags = ETag::parse_list("\"a\", W/\"b\", bogus");
+
+        assert_eq!(tags.len(), 2);
+        assert!(!tags[0].is_weak());
+        assert!(tags[1].is_weak());
+
+        let formatted: String = tags[1].clone().into();
+        assert_eq!(formatted, "W/\"b\"");
+    }
+
+    #[test]
+    fn strong_comparison_rejects_weak_tags() {
+        let weak = ETag::weak("a");
+
+        assert!(!weak.strong_eq(&ETag::strong("a")));
+        assert!(weak.weak_eq(&ETag::strong("a")));
+    }
+
+    #[test]
+    fn if_none_match_returns_not_modified_for_get_and_head() {
+        let etag = ETag::strong("v1");
+
+        for method in ["GET", "HEAD"] {
+            let req = request(method, &["If-None-Match: W/\"v1\""]);
+
+            assert!(matches!(
+                evaluate(&req, Some(&etag), None),
+                Some(HttpStatus::Http304NotModified)
+            ));
+        }
+
+        let req = request("PUT", &["If-None-Match: *"]);
+
+        assert!(matches!(
+            evaluate(&req, Some(&etag), None),
+            Some(HttpStatus::Http412PreconditionFailed)
+        ));
+    }
+
+    #[test]
+    fn if_modified_since_compares_seconds() {
+        let modified = date("Sun, 18 Oct 2026 10:00:00 GMT");
+
+        let req = request(
+            "HEAD",
+            &["If-Modified-Since: Sun, 18 Oct 2026 10:00:00 GMT"],
+        );
+        assert!(matches!(
+            evaluate(&req, None, Some(modified)),
+            Some(HttpStatus::Http304NotModified)
+        ));
+
+        let req = request("GET", &["If-Modified-Since: Sun, 18 Oct 2026 09:59:59 GMT"]);
+        assert!(evaluate(&req, None, Some(modified)).is_none());
+    }
+
+    #[test]
+    fn if_match_takes_precedence_over_if_unmodified_since() {
+        let etag = ETag::strong("v1");
+        let modified = date("Sun, 18 Oct 2026 10:00:00 GMT");
+
+        let req = request(
+            "PUT",
+            &[
+                "If-Match: \"v1\"",
+                "If-Unmodified-Since: Sun, 18 Oct 2026 09:00:00 GMT",
+            ],
+        );
+        assert!(evaluate(&req, Some(&etag), Some(modified)).is_none());
+
+        let req = request(
+            "PUT",
+            &["If-Unmodified-Since: Sun, 18 Oct 2026 09:00:00 GMT"],
+        );
+        assert!(matches!(
+            evaluate(&req, Some(&etag), Some(modified)),
+            Some(HttpStatus::Http412PreconditionFailed)
+        ));
+    }
+
+    #[test]
+    fn missing_representation_fails_if_match() {
+        let req = request("DELETE", &["If-Match: *"]);
+        assert!(matches!(
+            evaluate_current(&req, None),
+            Some(HttpStatus::Http412PreconditionFailed)
+        ));
+
+        let current = Validators::new().with_etag(ETag::strong("v2"));
+        let req = request("PUT", &["If-Match: \"v1\""]);
+        assert!(matches!(
+            evaluate_current(&req, Some(&current)),
+            Some(HttpStatus::Http412PreconditionFailed)
+        ));
+
+        let req = request("PUT", &[]);
+        assert!(evaluate_current(&req, None).is_none());
+    }
+}
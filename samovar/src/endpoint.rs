@@ -1,67 +1,352 @@
-use crate::common::Method;
-use crate::{common::ResponseTextWrapper, request::Request};
-use std::net::TcpStream;
-use std::ops::Deref;
+use crate::common::{HttpStatus, Method};
+use crate::compression::{CompressionConfig, CompressionPolicy};
+use crate::conditional::{self, Validator, Validators};
+use crate::guard::{self, Guard, GuardOutcome};
+use crate::middleware::{self, Middleware, MiddlewareRef};
+use crate::openapi::{EndpointDocs, Schema, SchemaRef};
+use crate::request::Request;
+use crate::response::{IntoResponse, Response};
+use crate::router::{join_path, HostPattern};
+use crate::samovar::Settings;
 use std::sync::{Arc, Mutex};
-use std::thread;
 
+#[cfg(feature = "async")]
+use std::{future::Future, panic, pin::Pin, thread};
+
+pub type Handler = Arc<dyn Fn(&Request) -> Response + Send + Sync>;
+
+#[cfg(feature = "async")]
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+#[cfg(feature = "async")]
+pub type AsyncHandler = Arc<dyn for<'a> Fn(&'a Request) -> BoxFuture<'a, Response> + Send + Sync>;
+
+#[cfg(feature = "async")]
+lazy_static! {
+    static ref RUNTIME: tokio::runtime::Runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+}
+
+#[derive(Clone)]
+pub enum Callable {
+    Sync(Handler),
+    #[cfg(feature = "async")]
+    Async(AsyncHandler),
+}
+
+#[derive(Clone)]
 pub struct Endpoint {
     uri: String,
-    callable: &'static (dyn Fn(&Request) -> ResponseTextWrapper + Sync),
+    callable: Callable,
     method: Method,
+    compression: CompressionPolicy,
+    host: HostPattern,
+    name: Option<String>,
+    guards: Vec<Guard>,
+    docs: EndpointDocs,
+    middleware: Vec<MiddlewareRef>,
+    validator: Option<Validator>,
 }
 
 impl Endpoint {
-    pub fn new_string_method(
-        uri: String,
-        callable: &'static (dyn Fn(&Request) -> ResponseTextWrapper + Sync),
-        method_string: String,
-    ) -> Self {
-        println!(
-            "Serving endpoint at {} with method {}",
-            &uri,
-            &method_string.to_uppercase()
-        );
+    pub fn new_string_method<F, R>(uri: String, callable: F, method_string: String) -> Self
+    where
+        F: Fn(&Request) -> R + Send + Sync + 'static,
+        R: IntoResponse,
+    {
+        Self::new(uri, callable, method_string.into())
+    }
+
+    pub fn new<F, R>(uri: String, callable: F, method: Method) -> Self
+    where
+        F: Fn(&Request) -> R + Send + Sync + 'static,
+        R: IntoResponse,
+    {
+        Self::from_handler(uri, Self::wrap(callable), method)
+    }
+
+    pub fn from_handler(uri: String, callable: Handler, method: Method) -> Self {
+        Self::from_callable(uri, Callable::Sync(callable), method)
+    }
+
+    #[cfg(feature = "async")]
+    pub fn new_async_string_method<F>(uri: String, callable: F, method_string: String) -> Self
+    where
+        F: for<'a> Fn(&'a Request) -> BoxFuture<'a, Response> + Send + Sync + 'static,
+    {
+        Self::new_async(uri, callable, method_string.into())
+    }
+
+    #[cfg(feature = "async")]
+    pub fn new_async<F>(uri: String, callable: F, method: Method) -> Self
+    where
+        F: for<'a> Fn(&'a Request) -> BoxFuture<'a, Response> + Send + Sync + 'static,
+    {
+        Self::from_callable(uri, Callable::Async(Arc::new(callable)), method)
+    }
+
+    fn from_callable(uri: String, callable: Callable, method: Method) -> Self {
+        let method_str: String = method.clone().into();
 
-        let method: Method = method_string.into();
+        println!("Serving endpoint at {} with method {}", uri, method_str);
 
         Endpoint {
             uri,
             callable,
             method,
+            compression: CompressionPolicy::Inherit,
+            host: HostPattern::Any,
+            name: None,
+            guards: vec![],
+            docs: EndpointDocs::default(),
+            middleware: vec![],
+            validator: None,
         }
     }
 
-    pub fn new(
+    pub fn from_arc<R>(
         uri: String,
-        callable: &'static (dyn Fn(&Request) -> ResponseTextWrapper + Sync),
+        callable: Arc<dyn Fn(&Request) -> R + Send + Sync>,
         method: Method,
-    ) -> Self {
-        let method_str: String = method.clone().into();
+    ) -> Self
+    where
+        R: IntoResponse + 'static,
+    {
+        Self::new(uri, move |r: &Request| callable(r), method)
+    }
 
-        println!("Serving endpoint at {} with method {}", &uri, method_str);
+    pub fn from_fn_mut<F, R>(uri: String, callable: F, method: Method) -> Self
+    where
+        F: FnMut(&Request) -> R + Send + 'static,
+        R: IntoResponse,
+    {
+        let callable = Mutex::new(callable);
 
-        Endpoint {
+        Self::new(
             uri,
-            callable,
+            move |r: &Request| {
+                let mut callable = callable.lock().unwrap_or_else(|e| e.into_inner());
+
+                callable(r)
+            },
             method,
+        )
+    }
+
+    fn wrap<F, R>(callable: F) -> Handler
+    where
+        F: Fn(&Request) -> R + Send + Sync + 'static,
+        R: IntoResponse,
+    {
+        Arc::new(move |r: &Request| callable(r).into_response())
+    }
+
+    pub fn with_compression(mut self, config: CompressionConfig) -> Self {
+        self.compression = CompressionPolicy::Enabled(config);
+
+        self
+    }
+
+    pub fn without_compression(mut self) -> Self {
+        self.compression = CompressionPolicy::Disabled;
+
+        self
+    }
+
+    pub fn with_host(mut self, host: HostPattern) -> Self {
+        self.host = host;
+
+        self
+    }
+
+    pub fn with_name(mut self, name: &str) -> Self {
+        self.name = Some(name.to_string());
+
+        self
+    }
+
+    pub fn with_guard(mut self, guard: Guard) -> Self {
+        self.guards.push(guard);
+
+        self
+    }
+
+    pub fn with_middleware<M: Middleware + 'static>(mut self, middleware: M) -> Self {
+        self.middleware.push(Arc::new(middleware));
+
+        self
+    }
+
+    pub fn with_outer_middleware(mut self, chain: &[MiddlewareRef]) -> Self {
+        self.middleware.splice(0..0, chain.iter().cloned());
+
+        self
+    }
+
+    pub fn with_validator<F>(mut self, validator: F) -> Self
+    where
+        F: Fn(&Request) -> Option<Validators> + Send + Sync + 'static,
+    {
+        self.validator = Some(Arc::new(validator));
+
+        self
+    }
+
+    pub fn with_summary(mut self, summary: &str) -> Self {
+        self.docs.summary = Some(summary.to_string());
+
+        self
+    }
+
+    pub fn with_request_schema<T: Schema>(mut self) -> Self {
+        self.docs.request = Some(SchemaRef::of::<T>());
+
+        self
+    }
+
+    pub fn with_response_schema<T: Schema>(mut self) -> Self {
+        self.docs.response = Some(SchemaRef::of::<T>());
+
+        self
+    }
+
+    pub fn hidden(mut self) -> Self {
+        self.docs.hidden = true;
+
+        self
+    }
+
+    pub fn with_prefix(mut self, prefix: &str) -> Self {
+        self.uri = join_path(prefix, &self.uri);
+
+        self
+    }
+
+    pub fn is_async(&self) -> bool {
+        !matches!(self.callable, Callable::Sync(_))
+    }
+
+    pub fn call(&self, request: &Request) -> Response {
+        match &self.callable {
+            Callable::Sync(callable) => callable(request),
+            #[cfg(feature = "async")]
+            Callable::Async(callable) => match tokio::runtime::Handle::try_current() {
+                Ok(_) => thread::scope(|s| s.spawn(|| RUNTIME.block_on(callable(request))).join())
+                    .unwrap_or_else(|e| panic::resume_unwind(e)),
+                Err(_) => RUNTIME.block_on(callable(request)),
+            },
         }
     }
 
-    pub fn serve_response(&self, stream: Mutex<TcpStream>, request: Arc<Request>) {
-        let callable_arc = Arc::new(self.callable);
+    fn middleware_chain<'a>(&'a self, settings: &'a Settings) -> Vec<&'a MiddlewareRef> {
+        settings
+            .middleware
+            .iter()
+            .chain(self.middleware.iter())
+            .collect()
+    }
+
+    pub fn respond(&self, request: &mut Request, settings: &Settings) -> Response {
+        let chain = self.middleware_chain(settings);
+
+        let mut resp = middleware::run(&chain, request, |r| {
+            self.check_preconditions(r).unwrap_or_else(|| self.call(r))
+        });
+
+        self.finish(request, settings, &mut resp);
+
+        resp
+    }
+
+    #[cfg(feature = "async")]
+    pub async fn respond_async(
+        self: Arc<Self>,
+        mut request: Request,
+        settings: Arc<Settings>,
+    ) -> Response {
+        let callable = match &self.callable {
+            Callable::Async(callable) => callable.clone(),
+            Callable::Sync(_) => {
+                return tokio::task::spawn_blocking(move || self.respond(&mut request, &settings))
+                    .await
+                    .unwrap_or_else(|_| HttpStatus::Http500InternalServerError.into_response());
+            }
+        };
+
+        let chain = self.middleware_chain(&settings);
+
+        let (entered, short_circuit) = middleware::run_before(&chain, &mut request);
 
-        thread::spawn(move || {
-            let mut stream_deref = stream.lock().unwrap();
-            let request_deref = request.deref();
-            let callabale_deref = *callable_arc.deref();
+        let precondition = match short_circuit {
+            Some(_) => None,
+            None => self.check_preconditions(&request),
+        };
 
-            let resp = (callabale_deref)(request_deref);
+        let mut resp = match short_circuit.or(precondition) {
+            Some(resp) => resp,
+            None => callable(&request).await,
+        };
 
-            resp.serve(&mut stream_deref);
+        middleware::run_after(&chain[..entered], &request, &mut resp);
+
+        self.finish(&request, &settings, &mut resp);
+
+        resp
+    }
+
+    #[cfg(feature = "async")]
+    pub fn spawn_async<F>(self: Arc<Self>, request: Request, settings: Arc<Settings>, done: F)
+    where
+        F: FnOnce(Response) + Send + 'static,
+    {
+        RUNTIME.spawn(async move {
+            let resp = tokio::spawn(self.respond_async(request, settings))
+                .await
+                .unwrap_or_else(|_| HttpStatus::Http500InternalServerError.into_response());
+
+            tokio::task::spawn_blocking(move || done(resp));
         });
     }
 
+    fn check_preconditions(&self, request: &Request) -> Option<Response> {
+        let current = match (&self.validator, self.method.is_safe()) {
+            (Some(validator), _) => validator(request),
+            (None, false) => None,
+            (None, true) => return None,
+        };
+
+        conditional::evaluate_current(request, current.as_ref())
+            .map(|status| Response::precondition(status, current.as_ref()))
+    }
+
+    fn finish(&self, request: &Request, settings: &Settings, resp: &mut Response) {
+        resp.apply_conditional(request, settings.auto_etag);
+        resp.apply_range(request);
+
+        let compression = match &self.compression {
+            CompressionPolicy::Inherit => settings.compression.clone(),
+            CompressionPolicy::Disabled => None,
+            CompressionPolicy::Enabled(config) => Some(config.clone()),
+        };
+
+        if let Some(config) = compression {
+            resp.compress(request, &config);
+        }
+
+        if request.get_method() == Method::HEAD {
+            resp.omit_body();
+        }
+    }
+
+    pub fn service_unavailable(retry_after: u64) -> Response {
+        let mut resp = HttpStatus::Http503ServiceUnavailable.into_response();
+        resp.set_header("Retry-After".to_string(), retry_after.to_string());
+
+        resp
+    }
+
     pub fn get_uri(&self) -> String {
         self.uri.clone()
     }
@@ -69,4 +354,127 @@ impl Endpoint {
     pub fn get_method(&self) -> Method {
         self.method.clone()
     }
+
+    pub fn get_host(&self) -> HostPattern {
+        self.host.clone()
+    }
+
+    pub fn get_name(&self) -> Option<String> {
+        self.name.clone()
+    }
+
+    pub fn get_docs(&self) -> EndpointDocs {
+        self.docs.clone()
+    }
+
+    pub fn get_middleware(&self) -> Vec<MiddlewareRef> {
+        self.middleware.clone()
+    }
+
+    pub fn get_guards(&self) -> Vec<Guard> {
+        self.guards.clone()
+    }
+
+    pub fn check_guards(&self, request: &Request) -> GuardOutcome {
+        guard::evaluate(&self.guards, request)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::test_request;
+
+    fn get() -> Request {
+        test_request("GET", "/", &[], "")
+    }
+
+    fn body(mut resp: Response) -> String {
+        let bytes = resp.compose().into_bytes();
+        let text = String::from_utf8(bytes).unwrap();
+
+        text.split("\r\n\r\n").nth(1).unwrap().to_string()
+    }
+
+    #[cfg(feature = "async")]
+    fn hello(_: &Request) -> BoxFuture<'_, Response> {
+        Box::pin(async { "hello".into_response() })
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn calls_async_handlers_from_inside_a_runtime() {
+        let endpoint = Endpoint::new_async("/".to_string(), hello, Method::GET);
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+
+        let resp = runtime.block_on(async { endpoint.call(&get()) });
+
+        assert_eq!(body(resp), "hello");
+        assert_eq!(body(endpoint.call(&get())), "hello");
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn runs_sync_handlers_off_the_runtime_workers() {
+        let caller = thread::current().id();
+
+        let endpoint = Arc::new(Endpoint::new(
+            "/".to_string(),
+            move |_: &Request| match thread::current().id() == caller {
+                true => "on the runtime thread",
+                false => "on a blocking thread",
+            },
+            Method::GET,
+        ));
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+
+        let resp = runtime.block_on(endpoint.respond_async(get(), Arc::new(Settings::default())));
+
+        assert_eq!(body(resp), "on a blocking thread");
+    }
+
+    #[test]
+    fn fn_mut_handlers_keep_their_state_across_calls() {
+        let mut hits = 0;
+
+        let endpoint = Endpoint::from_fn_mut(
+            "/".to_string(),
+            move |_: &Request| {
+                hits += 1;
+
+                hits.to_string()
+            },
+            Method::GET,
+        );
+
+        assert_eq!(body(endpoint.call(&get())), "1");
+        assert_eq!(body(endpoint.clone().call(&get())), "2");
+        assert_eq!(body(endpoint.call(&get())), "3");
+    }
+
+    #[test]
+    fn arc_handlers_share_captured_state() {
+        let counter = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let captured = counter.clone();
+
+        let handler: Arc<dyn Fn(&Request) -> String + Send + Sync> = Arc::new(move |_| {
+            let seen = captured.fetch_add(1, std::sync::atomic::Ordering::SeqCst) + 1;
+
+            seen.to_string()
+        });
+
+        let first = Endpoint::from_arc("/a".to_string(), handler.clone(), Method::GET);
+        let second = Endpoint::from_arc("/b".to_string(), handler, Method::GET);
+
+        assert_eq!(body(first.call(&get())), "1");
+        assert_eq!(body(second.call(&get())), "2");
+        assert_eq!(counter.load(std::sync::atomic::Ordering::SeqCst), 2);
+    }
 }
@@ -0,0 +1,471 @@
+use crate::common::HttpStatus;
+use crate::endpoint::Endpoint;
+use crate::parser::{IncrementalParser, ParseError};
+use crate::pool::ThreadPool;
+use crate::request::Request;
+use crate::response::IntoResponse;
+use crate::samovar::{Dispatch, Dispatcher};
+use mio::net::{TcpListener, TcpStream};
+use mio::{Events, Interest, Poll, Token, Waker};
+use std::collections::HashMap;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{Shutdown, SocketAddr};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+const LISTENER: Token = Token(0);
+const WAKER: Token = Token(1);
+const FIRST_CONNECTION: usize = 2;
+const EVENTS_CAPACITY: usize = 1024;
+const READ_CHUNK: usize = 8192;
+
+enum Phase {
+    Reading,
+    Handling,
+    Writing(Vec<u8>, usize),
+}
+
+struct Connection {
+    stream: TcpStream,
+    ip: SocketAddr,
+    parser: IncrementalParser,
+    phase: Phase,
+    deadline: Instant,
+}
+
+pub struct EventLoop {
+    poll: Poll,
+    listener: TcpListener,
+    waker: Arc<Waker>,
+    connections: HashMap<Token, Connection>,
+    next_token: usize,
+    dispatcher: Dispatcher,
+    pool: ThreadPool,
+    sender: Sender<(Token, Vec<u8>)>,
+    receiver: Receiver<(Token, Vec<u8>)>,
+}
+
+impl EventLoop {
+    pub fn bind(bind: &str, dispatcher: Dispatcher, pool: ThreadPool) -> io::Result<Self> {
+        let listener = std::net::TcpListener::bind(bind)?;
+        listener.set_nonblocking(true)?;
+
+        let mut listener = TcpListener::from_std(listener);
+
+        let poll = Poll::new()?;
+        poll.registry()
+            .register(&mut listener, LISTENER, Interest::READABLE)?;
+
+        let waker = Arc::new(Waker::new(poll.registry(), WAKER)?);
+
+        let (sender, receiver) = channel();
+
+        Ok(EventLoop {
+            poll,
+            listener,
+            waker,
+            connections: HashMap::new(),
+            next_token: FIRST_CONNECTION,
+            dispatcher,
+            pool,
+            sender,
+            receiver,
+        })
+    }
+
+    pub fn run(&mut self) -> io::Result<()> {
+        let mut events = Events::with_capacity(EVENTS_CAPACITY);
+
+        loop {
+            if let Err(e) = self.poll.poll(&mut events, self.next_timeout()) {
+                match e.kind() {
+                    ErrorKind::Interrupted => continue,
+                    _ => return Err(e),
+                }
+            }
+
+            for event in events.iter() {
+                match event.token() {
+                    LISTENER => self.accept(),
+                    WAKER => self.drain_handled(),
+                    token => {
+                        if event.is_readable() {
+                            self.read(token);
+                        }
+
+                        if event.is_writable() {
+                            self.write(token);
+                        }
+                    }
+                }
+            }
+
+            self.expire();
+        }
+    }
+
+    fn next_timeout(&self) -> Option<Duration> {
+        self.connections
+            .values()
+            .filter(|conn| matches!(conn.phase, Phase::Reading))
+            .map(|conn| conn.deadline.saturating_duration_since(Instant::now()))
+            .min()
+    }
+
+    fn expire(&mut self) {
+        let now = Instant::now();
+
+        let expired: Vec<Token> = self
+            .connections
+            .iter()
+            .filter(|(_, conn)| matches!(conn.phase, Phase::Reading) && conn.deadline <= now)
+            .map(|(token, _)| *token)
+            .collect();
+
+        for token in expired {
+            self.reject(token, ParseError::Timeout);
+        }
+    }
+
+    fn accept(&mut self) {
+        loop {
+            let (mut stream, ip) = match self.listener.accept() {
+                Ok(accepted) => accepted,
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return,
+                Err(e) => {
+                    println!("Could not accept connection: {}", e);
+                    return;
+                }
+            };
+
+            let token = Token(self.next_token);
+            self.next_token += 1;
+
+            if let Err(e) = self
+                .poll
+                .registry()
+                .register(&mut stream, token, Interest::READABLE)
+            {
+                println!("Could not register connection: {}", e);
+                continue;
+            }
+
+            let settings = self.dispatcher.get_settings();
+
+            self.connections.insert(
+                token,
+                Connection {
+                    stream,
+                    ip,
+                    parser: IncrementalParser::new(settings.max_body_size),
+                    phase: Phase::Reading,
+                    deadline: Instant::now() + settings.read_timeout,
+                },
+            );
+        }
+    }
+
+    fn read(&mut self, token: Token) {
+        let conn = match self.connections.get_mut(&token) {
+            Some(conn) if matches!(conn.phase, Phase::Reading) => conn,
+            _ => return,
+        };
+
+        let mut chunk = [0u8; READ_CHUNK];
+
+        let outcome = loop {
+            match conn.stream.read(&mut chunk) {
+                Ok(0) => match conn.parser.is_empty() {
+                    true => {
+                        self.close(token);
+                        return;
+                    }
+                    false => break Ok(true),
+                },
+                Ok(read) => match conn.parser.feed(&chunk[..read]) {
+                    Ok(true) => break Ok(true),
+                    Ok(false) => continue,
+                    Err(e) => break Err(e),
+                },
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break Ok(false),
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(_) => {
+                    self.close(token);
+                    return;
+                }
+            }
+        };
+
+        match outcome {
+            Ok(true) => self.dispatch(token),
+            Ok(false) => (),
+            Err(e) => self.reject(token, e),
+        }
+    }
+
+    fn dispatch(&mut self, token: Token) {
+        let conn = match self.connections.get_mut(&token) {
+            Some(conn) => conn,
+            None => return,
+        };
+
+        let max_body_size = self.dispatcher.get_settings().max_body_size;
+        let parser = std::mem::replace(&mut conn.parser, IncrementalParser::new(max_body_size));
+
+        let ip = conn.ip;
+
+        let request = match panic::catch_unwind(AssertUnwindSafe(|| parser.into_request(ip))) {
+            Ok(Ok(request)) => request,
+            Ok(Err(e)) => {
+                self.reject(token, e);
+                return;
+            }
+            Err(_) => {
+                self.reject(
+                    token,
+                    ParseError::Malformed("unreadable request".to_string()),
+                );
+                return;
+            }
+        };
+
+        let dispatched =
+            panic::catch_unwind(AssertUnwindSafe(|| self.dispatcher.dispatch(request)));
+
+        match dispatched {
+            Ok(Dispatch::Endpoint(endpoint, request)) => self.handle(token, endpoint, *request),
+            Ok(Dispatch::Respond(mut resp)) => self.respond(token, resp.compose().into_bytes()),
+            Err(_) => {
+                let mut resp = HttpStatus::Http500InternalServerError.into_response();
+
+                self.respond(token, resp.compose().into_bytes());
+            }
+        }
+    }
+
+    fn handle(&mut self, token: Token, endpoint: Arc<Endpoint>, mut request: Request) {
+        let settings = self.dispatcher.get_settings();
+
+        let sender = self.sender.clone();
+        let waker = self.waker.clone();
+        let retry_after = settings.pool.get_retry_after();
+
+        #[cfg(feature = "async")]
+        if endpoint.is_async() {
+            endpoint.spawn_async(request, settings, move |mut resp| {
+                if sender.send((token, resp.compose().into_bytes())).is_ok() {
+                    waker.wake().ok();
+                }
+            });
+
+            if let Some(conn) = self.connections.get_mut(&token) {
+                conn.phase = Phase::Handling;
+            }
+
+            return;
+        }
+
+        let accepted = self.pool.execute(move || {
+            let handled = panic::catch_unwind(AssertUnwindSafe(|| {
+                endpoint.respond(&mut request, &settings).compose()
+            }));
+
+            let wrapper = match handled {
+                Ok(wrapper) => wrapper,
+                Err(_) => HttpStatus::Http500InternalServerError
+                    .into_response()
+                    .compose(),
+            };
+
+            if sender.send((token, wrapper.into_bytes())).is_ok() {
+                waker.wake().ok();
+            }
+        });
+
+        match accepted {
+            true => {
+                if let Some(conn) = self.connections.get_mut(&token) {
+                    conn.phase = Phase::Handling;
+                }
+            }
+            false => {
+                println!("Worker pool queue is full, shedding request");
+
+                let mut resp = Endpoint::service_unavailable(retry_after);
+
+                self.respond(token, resp.compose().into_bytes());
+            }
+        }
+    }
+
+    fn drain_handled(&mut self) {
+        while let Ok((token, bytes)) = self.receiver.try_recv() {
+            self.respond(token, bytes);
+        }
+    }
+
+    fn reject(&mut self, token: Token, e: ParseError) {
+        println!("Request could not be parsed");
+
+        self.respond(token, e.into_response().compose().into_bytes());
+    }
+
+    fn respond(&mut self, token: Token, bytes: Vec<u8>) {
+        let conn = match self.connections.get_mut(&token) {
+            Some(conn) => conn,
+            None => return,
+        };
+
+        conn.phase = Phase::Writing(bytes, 0);
+
+        if self
+            .poll
+            .registry()
+            .reregister(&mut conn.stream, token, Interest::WRITABLE)
+            .is_err()
+        {
+            self.close(token);
+            return;
+        }
+
+        self.write(token);
+    }
+
+    fn write(&mut self, token: Token) {
+        let conn = match self.connections.get_mut(&token) {
+            Some(conn) => conn,
+            None => return,
+        };
+
+        let (bytes, written) = match &mut conn.phase {
+            Phase::Writing(bytes, written) => (bytes, written),
+            _ => return,
+        };
+
+        while *written < bytes.len() {
+            match conn.stream.write(&bytes[*written..]) {
+                Ok(0) => break,
+                Ok(n) => *written += n,
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => {
+                    println!("Could not write response: {}", e);
+                    break;
+                }
+            }
+        }
+
+        conn.stream.flush().ok();
+
+        self.close(token);
+    }
+
+    fn close(&mut self, token: Token) {
+        if let Some(mut conn) = self.connections.remove(&token) {
+            self.poll.registry().deregister(&mut conn.stream).ok();
+            conn.stream.shutdown(Shutdown::Both).ok();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::Method;
+    use crate::guard::Guard;
+    use crate::pool::PoolConfig;
+    use crate::samovar::Samovar;
+    use std::net::TcpStream as StdStream;
+    use std::thread;
+
+    fn start(samovar: Samovar) -> SocketAddr {
+        let (sender, receiver) = channel();
+
+        thread::spawn(move || {
+            let pool = ThreadPool::new(PoolConfig::new().workers(1));
+            let mut event_loop =
+                EventLoop::bind("127.0.0.1:0", samovar.dispatcher(), pool).unwrap();
+
+            sender
+                .send(event_loop.listener.local_addr().unwrap())
+                .unwrap();
+            event_loop.run().ok();
+        });
+
+        receiver.recv().unwrap()
+    }
+
+    fn send(addr: SocketAddr, raw: &[u8]) -> String {
+        let mut stream = StdStream::connect(addr).unwrap();
+        let mut response = vec![];
+
+        stream.write_all(raw).unwrap();
+        stream.read_to_end(&mut response).ok();
+
+        String::from_utf8_lossy(&response).to_string()
+    }
+
+    #[test]
+    fn keeps_serving_after_bad_requests() {
+        let samovar = Samovar::new("127.0.0.1", 0);
+
+        samovar
+            .add_endpoint(Endpoint::new(
+                "/a/*rest".to_string(),
+                |_: &Request| "a",
+                Method::GET,
+            ))
+            .unwrap();
+        samovar
+            .add_endpoint(
+                Endpoint::new("/boom".to_string(), |_: &Request| "boom", Method::GET)
+                    .with_guard(Guard::custom(|_| panic!("guard failed"))),
+            )
+            .unwrap();
+
+        let addr = start(samovar);
+
+        assert!(send(addr, b"GET /a/x?q=foo HTTP/1.1\r\n\r\n").starts_with("HTTP/1.1 200"));
+        assert!(send(addr, b"GET /a/@me HTTP/1.1\r\n\r\n").starts_with("HTTP/1.1 200"));
+        assert!(send(addr, b"\r\n\r\n").starts_with("HTTP/1.1 400"));
+        assert!(send(addr, b"GET /boom HTTP/1.1\r\n\r\n").starts_with("HTTP/1.1 500"));
+        assert!(send(addr, b"GET /a/b HTTP/1.1\r\n\r\n").starts_with("HTTP/1.1 200"));
+    }
+
+    #[test]
+    fn closes_stalled_connections() {
+        let mut samovar = Samovar::new("127.0.0.1", 0);
+
+        samovar.set_read_timeout(Duration::from_millis(100));
+        samovar
+            .add_endpoint(Endpoint::new(
+                "/".to_string(),
+                |_: &Request| "a",
+                Method::GET,
+            ))
+            .unwrap();
+
+        let addr = start(samovar);
+
+        let mut idle = StdStream::connect(addr).unwrap();
+        let mut stalled = StdStream::connect(addr).unwrap();
+        let mut response = vec![];
+
+        stalled.write_all(b"GET / HTTP/1.1\r\n").unwrap();
+        stalled
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        stalled.read_to_end(&mut response).unwrap();
+
+        assert!(String::from_utf8_lossy(&response).starts_with("HTTP/1.1 408"));
+
+        response.clear();
+        idle.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        idle.read_to_end(&mut response).unwrap();
+
+        assert!(String::from_utf8_lossy(&response).starts_with("HTTP/1.1 408"));
+        assert!(send(addr, b"GET / HTTP/1.1\r\n\r\n").starts_with("HTTP/1.1 200"));
+    }
+}
@@ -0,0 +1,151 @@
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+
+#[derive(Default)]
+pub struct Extensions {
+    values: HashMap<TypeId, Box<dyn Any + Send + Sync>>,
+}
+
+impl Extensions {
+    pub fn new() -> Self {
+        Extensions::default()
+    }
+
+    pub fn insert<T: Send + Sync + 'static>(&mut self, value: T) -> Option<T> {
+        self.values
+            .insert(TypeId::of::<T>(), Box::new(value))
+            .and_then(|previous| previous.downcast::<T>().ok())
+            .map(|previous| *previous)
+    }
+
+    pub fn get<T: Send + Sync + 'static>(&self) -> Option<&T> {
+        self.values
+            .get(&TypeId::of::<T>())
+            .and_then(|value| value.downcast_ref::<T>())
+    }
+
+    pub fn get_mut<T: Send + Sync + 'static>(&mut self) -> Option<&mut T> {
+        self.values
+            .get_mut(&TypeId::of::<T>())
+            .and_then(|value| value.downcast_mut::<T>())
+    }
+
+    pub fn remove<T: Send + Sync + 'static>(&mut self) -> Option<T> {
+        self.values
+            .remove(&TypeId::of::<T>())
+            .and_then(|value| value.downcast::<T>().ok())
+            .map(|value| *value)
+    }
+
+    pub fn contains<T: Send + Sync + 'static>(&self) -> bool {
+        self.values.contains_key(&TypeId::of::<T>())
+    }
+
+    pub fn extend(&mut self, other: Extensions) {
+        self.values.extend(other.values);
+    }
+
+    pub fn clear(&mut self) {
+        self.values.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::Method;
+    use crate::endpoint::Endpoint;
+    use crate::middleware::{After, Before};
+    use crate::parser::test_request;
+    use crate::request::Request;
+    use crate::response::{IntoResponse, Response};
+    use crate::samovar::Settings;
+
+    #[derive(Clone, Debug, PartialEq)]
+    struct User(String);
+
+    #[test]
+    fn stores_one_value_per_type() {
+        let mut extensions = Extensions::new();
+
+        assert_eq!(extensions.insert(User("a".to_string())), None);
+        assert_eq!(extensions.insert(5u32), None);
+        assert_eq!(extensions.get::<User>(), Some(&User("a".to_string())));
+        assert_eq!(extensions.get::<u32>(), Some(&5));
+        assert_eq!(extensions.get::<u64>(), None);
+        assert_eq!(extensions.len(), 2);
+    }
+
+    #[test]
+    fn overwrites_and_removes_values() {
+        let mut extensions = Extensions::new();
+
+        extensions.insert(User("a".to_string()));
+
+        assert_eq!(
+            extensions.insert(User("b".to_string())),
+            Some(User("a".to_string()))
+        );
+
+        extensions.get_mut::<User>().unwrap().0.push('!');
+
+        assert_eq!(extensions.remove::<User>(), Some(User("b!".to_string())));
+        assert!(!extensions.contains::<User>());
+        assert!(extensions.is_empty());
+    }
+
+    #[test]
+    fn middleware_passes_values_to_handlers() {
+        let endpoint = Endpoint::new(
+            "/".to_string(),
+            |r: &Request| match r.extensions().get::<User>() {
+                Some(user) => user.0.clone(),
+                None => "anonymous".to_string(),
+            },
+            Method::GET,
+        )
+        .with_middleware(Before(|r: &mut Request| {
+            r.extensions_mut().insert(User("tea".to_string()));
+
+            None
+        }));
+
+        let mut request = test_request("GET", "/", &[], "");
+        let mut resp = endpoint.respond(&mut request, &Settings::default());
+        let text = String::from_utf8(resp.compose().into_bytes()).unwrap();
+
+        assert!(text.ends_with("\r\n\r\ntea"));
+        assert!(request.extensions().contains::<User>());
+    }
+
+    #[test]
+    fn handlers_pass_values_to_after_hooks() {
+        let endpoint = Endpoint::new(
+            "/".to_string(),
+            |_: &Request| {
+                let mut resp = "ok".into_response();
+                resp.extensions_mut().insert(User("handler".to_string()));
+
+                resp
+            },
+            Method::GET,
+        )
+        .with_middleware(After(|_: &Request, resp: &mut Response| {
+            if let Some(user) = resp.extensions().get::<User>().cloned() {
+                resp.set_header("X-User".to_string(), user.0);
+            }
+        }));
+
+        let resp = endpoint.respond(&mut test_request("GET", "/", &[], ""), &Settings::default());
+
+        assert_eq!(resp.get_header("X-User").as_deref(), Some("handler"));
+    }
+}
@@ -0,0 +1,685 @@
+use crate::common::{HttpStatus, MimeType, Params, RequestBodyType};
+use crate::request::Request;
+use crate::response::{IntoResponse, Response};
+use serde::de::value::{MapDeserializer, SeqDeserializer};
+use serde::de::{self, DeserializeOwned, IntoDeserializer, Visitor};
+use serde::{forward_to_deserialize_any, Deserializer};
+use std::net::IpAddr;
+use std::ops::Deref;
+use std::sync::Arc;
+
+pub use crate::response::Json;
+
+pub trait FromRequest: Sized {
+    type Rejection: IntoResponse;
+
+    fn from_request(request: &Request) -> Result<Self, Self::Rejection>;
+}
+
+pub enum Rejection {
+    MissingHeader(String),
+    InvalidHeader(String, String),
+    InvalidQuery(String),
+    InvalidPath(String),
+    UnsupportedMediaType(MimeType),
+    InvalidBody(String),
+    MissingState(String),
+    MissingExtension(String),
+}
+
+impl Rejection {
+    pub fn get_status(&self) -> HttpStatus {
+        match self {
+            Rejection::MissingHeader(_)
+            | Rejection::InvalidHeader(_, _)
+            | Rejection::InvalidQuery(_) => HttpStatus::Http400BadRequest,
+            Rejection::InvalidPath(_) => HttpStatus::Http404NotFound,
+            Rejection::UnsupportedMediaType(_) => HttpStatus::Http415UnsupportedMediaType,
+            Rejection::InvalidBody(_) => HttpStatus::Http422UnprocessableEntity,
+            Rejection::MissingState(_) | Rejection::MissingExtension(_) => {
+                HttpStatus::Http500InternalServerError
+            }
+        }
+    }
+}
+
+impl From<Rejection> for String {
+    fn from(r: Rejection) -> Self {
+        match r {
+            Rejection::MissingHeader(name) => format!("Missing header: {}", name),
+            Rejection::InvalidHeader(name, value) => {
+                format!("Invalid value for header {}: {}", name, value)
+            }
+            Rejection::InvalidQuery(e) => format!("Invalid query string: {}", e),
+            Rejection::InvalidPath(e) => format!("Invalid path parameters: {}", e),
+            Rejection::UnsupportedMediaType(expected) => {
+                let expected: String = expected.into();
+
+                format!("Expected request with Content-Type: {}", expected)
+            }
+            Rejection::InvalidBody(e) => format!("Invalid request body: {}", e),
+            Rejection::MissingState(name) => format!("State not registered: {}", name),
+            Rejection::MissingExtension(name) => format!("Extension not set: {}", name),
+        }
+    }
+}
+
+impl IntoResponse for Rejection {
+    fn into_response(self) -> Response {
+        let status = self.get_status();
+        let message: String = self.into();
+
+        Response::new_string(message, MimeType::TextPlain, status)
+    }
+}
+
+impl<T: FromRequest> FromRequest for Option<T> {
+    type Rejection = Rejection;
+
+    fn from_request(request: &Request) -> Result<Self, Self::Rejection> {
+        Ok(T::from_request(request).ok())
+    }
+}
+
+impl<T: DeserializeOwned> FromRequest for Json<T> {
+    type Rejection = Rejection;
+
+    fn from_request(request: &Request) -> Result<Self, Self::Rejection> {
+        if request.get_content_type() != MimeType::ApplicationJson {
+            return Err(Rejection::UnsupportedMediaType(MimeType::ApplicationJson));
+        }
+
+        let decoded = match request.get_body().content {
+            RequestBodyType::Json(value) => serde_json::from_value(value),
+            RequestBodyType::Str(text) => serde_json::from_str(&text),
+            RequestBodyType::Bytes(bytes) => serde_json::from_slice(&bytes),
+        };
+
+        decoded
+            .map(Json)
+            .map_err(|e| Rejection::InvalidBody(e.to_string()))
+    }
+}
+
+pub struct Query<T>(pub T);
+
+impl<T: DeserializeOwned> FromRequest for Query<T> {
+    type Rejection = Rejection;
+
+    fn from_request(request: &Request) -> Result<Self, Self::Rejection> {
+        let location = request.get_location();
+
+        let query = location
+            .split_once('?')
+            .map(|(_, query)| query.split('#').next().unwrap_or(""))
+            .unwrap_or("");
+
+        serde_urlencoded::from_str(query)
+            .map(Query)
+            .map_err(|e| Rejection::InvalidQuery(e.to_string()))
+    }
+}
+
+pub struct Form<T>(pub T);
+
+impl<T: DeserializeOwned> FromRequest for Form<T> {
+    type Rejection = Rejection;
+
+    fn from_request(request: &Request) -> Result<Self, Self::Rejection> {
+        let form = MimeType::from("application/x-www-form-urlencoded");
+
+        if request.get_content_type() != form {
+            return Err(Rejection::UnsupportedMediaType(form));
+        }
+
+        match request.get_body().content {
+            RequestBodyType::Str(body) => serde_urlencoded::from_str(&body)
+                .map(Form)
+                .map_err(|e| Rejection::InvalidBody(e.to_string())),
+            _ => Err(Rejection::InvalidBody("expected a UTF-8 form".to_string())),
+        }
+    }
+}
+
+pub struct Path<T>(pub T);
+
+impl<T: DeserializeOwned> FromRequest for Path<T> {
+    type Rejection = Rejection;
+
+    fn from_request(request: &Request) -> Result<Self, Self::Rejection> {
+        let params = request.get_path_params();
+
+        T::deserialize(PathParams(&params))
+            .map(Path)
+            .map_err(|e| Rejection::InvalidPath(e.to_string()))
+    }
+}
+
+pub trait TypedHeader: Sized {
+    fn name() -> &'static str;
+
+    fn decode(value: &str) -> Option<Self>;
+}
+
+macro_rules! typed_header {
+    ($($t:ident($inner:ty) => $name:expr),*) => {
+        $(
+            pub struct $t(pub $inner);
+
+            impl TypedHeader for $t {
+                fn name() -> &'static str {
+                    $name
+                }
+
+                fn decode(value: &str) -> Option<Self> {
+                    value.trim().parse::<$inner>().ok().map($t)
+                }
+            }
+        )*
+    };
+}
+
+typed_header!(
+    Accept(String) => "Accept",
+    Authorization(String) => "Authorization",
+    ContentLength(u64) => "Content-Length",
+    Cookie(String) => "Cookie",
+    Origin(String) => "Origin",
+    Referer(String) => "Referer",
+    UserAgent(String) => "User-Agent"
+);
+
+pub struct Header<T>(pub T);
+
+impl<T: TypedHeader> FromRequest for Header<T> {
+    type Rejection = Rejection;
+
+    fn from_request(request: &Request) -> Result<Self, Self::Rejection> {
+        let value = request
+            .get_header(T::name())
+            .ok_or_else(|| Rejection::MissingHeader(T::name().to_string()))?;
+
+        T::decode(&value)
+            .map(Header)
+            .ok_or_else(|| Rejection::InvalidHeader(T::name().to_string(), value))
+    }
+}
+
+pub struct State<T>(pub Arc<T>);
+
+impl<T: Send + Sync + 'static> FromRequest for State<T> {
+    type Rejection = Rejection;
+
+    fn from_request(request: &Request) -> Result<Self, Self::Rejection> {
+        request
+            .get_state::<T>()
+            .map(State)
+            .ok_or_else(|| Rejection::MissingState(std::any::type_name::<T>().to_string()))
+    }
+}
+
+pub struct Extension<T>(pub T);
+
+impl<T: Clone + Send + Sync + 'static> FromRequest for Extension<T> {
+    type Rejection = Rejection;
+
+    fn from_request(request: &Request) -> Result<Self, Self::Rejection> {
+        request
+            .extensions()
+            .get::<T>()
+            .cloned()
+            .map(Extension)
+            .ok_or_else(|| Rejection::MissingExtension(std::any::type_name::<T>().to_string()))
+    }
+}
+
+pub struct ClientIp(pub IpAddr);
+
+impl FromRequest for ClientIp {
+    type Rejection = Rejection;
+
+    fn from_request(request: &Request) -> Result<Self, Self::Rejection> {
+        Ok(ClientIp(request.get_ip().ip()))
+    }
+}
+
+macro_rules! deref_extractor {
+    ($($t:ident => $target:ty),*) => {
+        $(
+            impl<T> Deref for $t<T> {
+                type Target = $target;
+
+                fn deref(&self) -> &Self::Target {
+                    &self.0
+                }
+            }
+        )*
+    };
+}
+
+deref_extractor!(Query => T, Form => T, Path => T, Header => T, State => T, Extension => T);
+
+struct ParamValue<'a>(&'a str);
+
+macro_rules! parse_value {
+    ($($method:ident => $visit:ident),*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+                match self.0.parse() {
+                    Ok(value) => visitor.$visit(value),
+                    Err(_) => Err(de::Error::custom(format!("cannot parse {:?}", self.0))),
+                }
+            }
+        )*
+    };
+}
+
+impl<'de, 'a> Deserializer<'de> for ParamValue<'a> {
+    type Error = de::value::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_str(self.0)
+    }
+
+    parse_value!(
+        deserialize_bool => visit_bool,
+        deserialize_i8 => visit_i8,
+        deserialize_i16 => visit_i16,
+        deserialize_i32 => visit_i32,
+        deserialize_i64 => visit_i64,
+        deserialize_u8 => visit_u8,
+        deserialize_u16 => visit_u16,
+        deserialize_u32 => visit_u32,
+        deserialize_u64 => visit_u64,
+        deserialize_f32 => visit_f32,
+        deserialize_f64 => visit_f64,
+        deserialize_char => visit_char
+    );
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_enum(self.0.into_deserializer())
+    }
+
+    forward_to_deserialize_any! {
+        i128 u128 str string bytes byte_buf unit unit_struct seq tuple
+        tuple_struct map struct identifier ignored_any
+    }
+}
+
+impl<'de, 'a> IntoDeserializer<'de, de::value::Error> for ParamValue<'a> {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self::Deserializer {
+        self
+    }
+}
+
+struct PathParams<'a>(&'a [Params]);
+
+impl<'a> PathParams<'a> {
+    fn single(&self) -> Result<ParamValue<'a>, de::value::Error> {
+        match self.0 {
+            [param] => Ok(ParamValue(&param.value)),
+            _ => Err(de::Error::custom(format!(
+                "expected 1 path parameter, found {}",
+                self.0.len()
+            ))),
+        }
+    }
+
+    fn visit_map<'de, V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, de::value::Error> {
+        let mut map = MapDeserializer::new(
+            self.0
+                .iter()
+                .map(|p| (p.key.as_str(), ParamValue(p.value.as_str()))),
+        );
+
+        let value = visitor.visit_map(&mut map)?;
+        map.end()?;
+
+        Ok(value)
+    }
+
+    fn visit_seq<'de, V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, de::value::Error> {
+        let mut seq = SeqDeserializer::new(self.0.iter().map(|p| ParamValue(p.value.as_str())));
+
+        let value = visitor.visit_seq(&mut seq)?;
+        seq.end()?;
+
+        Ok(value)
+    }
+}
+
+macro_rules! single_param {
+    ($($method:ident),*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+                self.single()?.$method(visitor)
+            }
+        )*
+    };
+}
+
+impl<'de, 'a> Deserializer<'de> for PathParams<'a> {
+    type Error = de::value::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.visit_map(visitor)
+    }
+
+    single_param!(
+        deserialize_bool,
+        deserialize_i8,
+        deserialize_i16,
+        deserialize_i32,
+        deserialize_i64,
+        deserialize_i128,
+        deserialize_u8,
+        deserialize_u16,
+        deserialize_u32,
+        deserialize_u64,
+        deserialize_u128,
+        deserialize_f32,
+        deserialize_f64,
+        deserialize_char,
+        deserialize_str,
+        deserialize_string,
+        deserialize_bytes,
+        deserialize_byte_buf,
+        deserialize_option,
+        deserialize_unit,
+        deserialize_identifier,
+        deserialize_ignored_any
+    );
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.single()?.deserialize_unit_struct(name, visitor)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.single()?.deserialize_enum(name, variants, visitor)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.visit_seq(visitor)
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.visit_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.visit_seq(visitor)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.visit_map(visitor)
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.visit_map(visitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::test_request;
+    use crate::state::StateMap;
+    use serde::Deserialize;
+
+    fn request(content_type: &str, body: &str) -> Request {
+        let header = format!("Content-Type: {}", content_type);
+
+        test_request("POST", "/doc", &[&header], body)
+    }
+
+    fn status<T>(outcome: Result<T, Rejection>) -> Option<HttpStatus> {
+        outcome.err().map(|rejection| rejection.get_status())
+    }
+
+    #[test]
+    fn decodes_json_bodies() {
+        let Json(numbers) = Json::<Vec<u32>>::from_request(&request("application/json", "[1, 2]"))
+            .ok()
+            .unwrap();
+
+        assert_eq!(numbers, vec![1, 2]);
+    }
+
+    #[test]
+    fn rejects_malformed_json_as_unprocessable() {
+        let outcome = Json::<Vec<u32>>::from_request(&request("application/json", "[1,"));
+
+        assert!(matches!(
+            status(outcome),
+            Some(HttpStatus::Http422UnprocessableEntity)
+        ));
+    }
+
+    #[test]
+    fn rejects_json_of_the_wrong_shape_as_unprocessable() {
+        let outcome = Json::<Vec<u32>>::from_request(&request("application/json", "{\"a\": 1}"));
+
+        assert!(matches!(
+            status(outcome),
+            Some(HttpStatus::Http422UnprocessableEntity)
+        ));
+    }
+
+    #[test]
+    fn rejects_other_content_types() {
+        let outcome = Json::<Vec<u32>>::from_request(&request("text/plain", "[1, 2]"));
+
+        assert!(matches!(
+            status(outcome),
+            Some(HttpStatus::Http415UnsupportedMediaType)
+        ));
+    }
+
+    fn get(location: &str, headers: &[&str]) -> Request {
+        test_request("GET", location, headers, "")
+    }
+
+    #[derive(Deserialize)]
+    struct Search {
+        q: String,
+        page: u32,
+    }
+
+    #[test]
+    fn extracts_queries() {
+        let Query(search) = Query::<Search>::from_request(&get("/s?q=tea%20pot&page=2", &[]))
+            .ok()
+            .unwrap();
+
+        assert_eq!(search.q, "tea pot");
+        assert_eq!(search.page, 2);
+
+        let outcome = Query::<Search>::from_request(&get("/s?q=tea&page=two", &[]));
+
+        assert!(matches!(
+            status(outcome),
+            Some(HttpStatus::Http400BadRequest)
+        ));
+    }
+
+    #[test]
+    fn extracts_path_parameters() {
+        let mut req = get("/users/7/posts/tea", &[]);
+
+        req.set_path_params(vec![
+            Params {
+                key: "id".to_string(),
+                value: "7".to_string(),
+            },
+            Params {
+                key: "slug".to_string(),
+                value: "tea".to_string(),
+            },
+        ]);
+
+        let Path((id, slug)) = Path::<(u32, String)>::from_request(&req).ok().unwrap();
+
+        assert_eq!(id, 7);
+        assert_eq!(slug, "tea");
+
+        req.set_path_params(vec![Params {
+            key: "id".to_string(),
+            value: "seven".to_string(),
+        }]);
+
+        let outcome = Path::<u32>::from_request(&req);
+
+        assert!(matches!(status(outcome), Some(HttpStatus::Http404NotFound)));
+    }
+
+    #[test]
+    fn extracts_forms() {
+        let form = "application/x-www-form-urlencoded";
+
+        let Form(search) = Form::<Search>::from_request(&request(form, "q=tea+pot&page=3"))
+            .ok()
+            .unwrap();
+
+        assert_eq!(search.q, "tea pot");
+        assert_eq!(search.page, 3);
+
+        let outcome = Form::<Search>::from_request(&request(form, "q=tea"));
+
+        assert!(matches!(
+            status(outcome),
+            Some(HttpStatus::Http422UnprocessableEntity)
+        ));
+
+        let outcome = Form::<Search>::from_request(&request("application/json", "{}"));
+
+        assert!(matches!(
+            status(outcome),
+            Some(HttpStatus::Http415UnsupportedMediaType)
+        ));
+    }
+
+    #[test]
+    fn extracts_typed_headers() {
+        let req = get("/", &["User-Agent: curl/8.0", "Content-Length: abc"]);
+
+        let Header(UserAgent(agent)) = Header::<UserAgent>::from_request(&req).ok().unwrap();
+
+        assert_eq!(agent, "curl/8.0");
+
+        assert!(matches!(
+            status(Header::<ContentLength>::from_request(&req)),
+            Some(HttpStatus::Http400BadRequest)
+        ));
+        assert!(matches!(
+            status(Header::<Origin>::from_request(&req)),
+            Some(HttpStatus::Http400BadRequest)
+        ));
+    }
+
+    #[test]
+    fn extracts_shared_state() {
+        let mut state = StateMap::new();
+        state.insert(41u32);
+
+        let mut req = get("/", &[]);
+        req.set_state(Arc::new(state));
+
+        let State(value) = State::<u32>::from_request(&req).ok().unwrap();
+
+        assert_eq!(*value, 41);
+
+        assert!(matches!(
+            status(State::<String>::from_request(&req)),
+            Some(HttpStatus::Http500InternalServerError)
+        ));
+    }
+
+    #[test]
+    fn extracts_extensions() {
+        let mut req = get("/", &[]);
+        req.extensions_mut().insert("user".to_string());
+
+        let Extension(user) = Extension::<String>::from_request(&req).ok().unwrap();
+
+        assert_eq!(user, "user");
+
+        assert!(matches!(
+            status(Extension::<u32>::from_request(&req)),
+            Some(HttpStatus::Http500InternalServerError)
+        ));
+    }
+
+    #[test]
+    fn extracts_the_client_ip() {
+        let ClientIp(ip) = ClientIp::from_request(&get("/", &[])).ok().unwrap();
+
+        assert_eq!(ip, "127.0.0.1".parse::<IpAddr>().unwrap());
+
+        let missing = Option::<Header<Origin>>::from_request(&get("/", &[]));
+
+        assert!(matches!(missing, Ok(None)));
+    }
+
+    #[test]
+    fn rejections_become_responses_with_their_status() {
+        let response = Rejection::InvalidPath("bad".to_string()).into_response();
+
+        assert!(matches!(response.get_status(), HttpStatus::Http404NotFound));
+
+        let mut response =
+            Rejection::UnsupportedMediaType(MimeType::ApplicationJson).into_response();
+        let body = String::from_utf8(response.compose().into_bytes()).unwrap();
+
+        assert!(body.contains("Expected request with Content-Type: application/json"));
+    }
+}
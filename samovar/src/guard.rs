@@ -0,0 +1,192 @@
+use crate::common::MimeType;
+use crate::request::Request;
+use crate::router::HostPattern;
+use regex::Regex;
+use std::sync::Arc;
+
+#[derive(Clone)]
+pub enum Guard {
+    Header(String, String),
+    HeaderRegex(String, Regex),
+    ContentType(MimeType),
+    Accepts(MimeType),
+    QueryParam(String),
+    Host(HostPattern),
+    Custom(Arc<dyn Fn(&Request) -> bool + Send + Sync>),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum GuardOutcome {
+    Pass,
+    ContentType,
+    Fail,
+}
+
+impl Guard {
+    pub fn header(key: &str, value: &str) -> Self {
+        Guard::Header(key.to_lowercase(), value.to_string())
+    }
+
+    pub fn header_regex(key: &str, pattern: &str) -> Self {
+        let regex = Regex::new(pattern).expect("Invalid header guard regex");
+
+        Guard::HeaderRegex(key.to_lowercase(), regex)
+    }
+
+    pub fn content_type(mime: MimeType) -> Self {
+        Guard::ContentType(mime)
+    }
+
+    pub fn accepts(mime: MimeType) -> Self {
+        Guard::Accepts(mime)
+    }
+
+    pub fn query_param(key: &str) -> Self {
+        Guard::QueryParam(key.to_string())
+    }
+
+    pub fn host(pattern: &str) -> Self {
+        Guard::Host(HostPattern::parse(pattern))
+    }
+
+    pub fn custom<F: Fn(&Request) -> bool + Send + Sync + 'static>(predicate: F) -> Self {
+        Guard::Custom(Arc::new(predicate))
+    }
+
+    pub fn check(&self, request: &Request) -> bool {
+        match self {
+            Guard::Header(key, value) => request.get_header(key).as_deref() == Some(value),
+            Guard::HeaderRegex(key, regex) => match request.get_header(key) {
+                Some(header) => regex.is_match(&header),
+                None => false,
+            },
+            Guard::ContentType(mime) => request.get_content_type() == *mime,
+            Guard::Accepts(mime) => request.accepts(mime),
+            Guard::QueryParam(key) => request.get_params().iter().any(|p| p.key == *key),
+            Guard::Host(pattern) => pattern.matches(&request.get_host()),
+            Guard::Custom(predicate) => predicate(request),
+        }
+    }
+
+    pub fn is_content_type(&self) -> bool {
+        matches!(self, Guard::ContentType(_))
+    }
+
+    pub fn same_as(&self, other: &Guard) -> bool {
+        match (self, other) {
+            (Guard::Header(k1, v1), Guard::Header(k2, v2)) => k1 == k2 && v1 == v2,
+            (Guard::HeaderRegex(k1, r1), Guard::HeaderRegex(k2, r2)) => {
+                k1 == k2 && r1.as_str() == r2.as_str()
+            }
+            (Guard::ContentType(m1), Guard::ContentType(m2)) => m1 == m2,
+            (Guard::Accepts(m1), Guard::Accepts(m2)) => m1 == m2,
+            (Guard::QueryParam(k1), Guard::QueryParam(k2)) => k1 == k2,
+            (Guard::Host(h1), Guard::Host(h2)) => h1 == h2,
+            (Guard::Custom(p1), Guard::Custom(p2)) => {
+                Arc::as_ptr(p1) as *const () == Arc::as_ptr(p2) as *const ()
+            }
+            _ => false,
+        }
+    }
+}
+
+pub fn same_guards(a: &[Guard], b: &[Guard]) -> bool {
+    a.len() == b.len() && a.iter().all(|g| b.iter().any(|other| g.same_as(other)))
+}
+
+pub fn evaluate(guards: &[Guard], request: &Request) -> GuardOutcome {
+    let mut content_type_failed = false;
+
+    for guard in guards.iter() {
+        if guard.check(request) {
+            continue;
+        }
+
+        match guard.is_content_type() {
+            true => content_type_failed = true,
+            false => return GuardOutcome::Fail,
+        }
+    }
+
+    match content_type_failed {
+        true => GuardOutcome::ContentType,
+        false => GuardOutcome::Pass,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::test_request;
+
+    fn request(location: &str, headers: &[&str]) -> Request {
+        test_request(
+            "GET",
+            location,
+            &[&["Host: api.example.com"], headers].concat(),
+            "",
+        )
+    }
+
+    #[test]
+    fn header_guards_match_values_and_patterns() {
+        let req = request("/", &["X-Version: 2", "User-Agent: curl/8.0"]);
+
+        assert!(Guard::header("x-version", "2").check(&req));
+        assert!(!Guard::header("X-Version", "3").check(&req));
+        assert!(!Guard::header("X-Missing", "2").check(&req));
+        assert!(Guard::header_regex("User-Agent", "^curl/").check(&req));
+        assert!(!Guard::header_regex("User-Agent", "^wget/").check(&req));
+    }
+
+    #[test]
+    fn query_guards_see_every_key() {
+        assert!(Guard::query_param("q").check(&request("/a?q=foo", &[])));
+        assert!(Guard::query_param("b").check(&request("/a?a=1&b=2", &[])));
+        assert!(Guard::query_param("flag").check(&request("/a?flag", &[])));
+        assert!(Guard::query_param("a b").check(&request("/a?a+b=1", &[])));
+        assert!(!Guard::query_param("q").check(&request("/a?qq=1", &[])));
+        assert!(!Guard::query_param("q").check(&request("/a", &[])));
+    }
+
+    #[test]
+    fn host_accepts_and_custom_guards() {
+        let req = request("/", &["Accept: application/json"]);
+
+        assert!(Guard::host("*.example.com").check(&req));
+        assert!(!Guard::host("other.test").check(&req));
+        assert!(Guard::accepts(MimeType::ApplicationJson).check(&req));
+        assert!(!Guard::accepts(MimeType::TextHtml).check(&req));
+        assert!(Guard::custom(|r| r.get_location() == "/").check(&req));
+    }
+
+    #[test]
+    fn evaluate_separates_content_type_failures() {
+        let req = request("/", &["Content-Type: text/plain", "X-Version: 2"]);
+
+        let json = Guard::content_type(MimeType::ApplicationJson);
+        let version = Guard::header("X-Version", "2");
+        let missing = Guard::header("X-Missing", "1");
+
+        assert_eq!(evaluate(&[], &req), GuardOutcome::Pass);
+        assert_eq!(
+            evaluate(&[Guard::header("X-Missing", "1")], &req),
+            GuardOutcome::Fail
+        );
+        assert_eq!(
+            evaluate(&[json.clone(), version], &req),
+            GuardOutcome::ContentType
+        );
+        assert_eq!(evaluate(&[json, missing], &req), GuardOutcome::Fail);
+    }
+
+    #[test]
+    fn same_guards_ignores_order() {
+        let a = [Guard::header("X-A", "1"), Guard::query_param("q")];
+        let b = [Guard::query_param("q"), Guard::header("x-a", "1")];
+
+        assert!(same_guards(&a, &b));
+        assert!(!same_guards(&a, &b[..1]));
+        assert!(!same_guards(&[Guard::header("X-A", "2")], &a[..1]));
+    }
+}
@@ -3,13 +3,30 @@
 #[macro_use]
 extern crate lazy_static;
 
+pub use serde_json;
+
+#[cfg(feature = "async")]
+pub mod asyncserver;
 pub mod common;
+pub mod compression;
+pub mod conditional;
 pub mod endpoint;
+pub mod eventloop;
+pub mod extensions;
+pub mod extract;
+pub mod guard;
+pub mod middleware;
+pub mod negotiation;
+pub mod openapi;
 pub mod parser;
+pub mod pool;
+pub mod range;
 pub mod request;
 pub mod response;
+pub mod router;
 pub mod samovar;
 pub mod session;
+pub mod state;
 pub mod staticserver;
 
 #[cfg(test)]
@@ -0,0 +1,178 @@
+use crate::request::Request;
+use crate::response::Response;
+use std::sync::Arc;
+
+pub trait Middleware: Send + Sync {
+    fn before(&self, _request: &mut Request) -> Option<Response> {
+        None
+    }
+
+    fn after(&self, _request: &Request, _response: &mut Response) {}
+}
+
+pub type MiddlewareRef = Arc<dyn Middleware>;
+
+pub struct Before<F>(pub F);
+
+impl<F> Middleware for Before<F>
+where
+    F: Fn(&mut Request) -> Option<Response> + Send + Sync,
+{
+    fn before(&self, request: &mut Request) -> Option<Response> {
+        (self.0)(request)
+    }
+}
+
+pub struct After<F>(pub F);
+
+impl<F> Middleware for After<F>
+where
+    F: Fn(&Request, &mut Response) + Send + Sync,
+{
+    fn after(&self, request: &Request, response: &mut Response) {
+        (self.0)(request, response)
+    }
+}
+
+pub fn run_before(chain: &[&MiddlewareRef], request: &mut Request) -> (usize, Option<Response>) {
+    for (i, middleware) in chain.iter().enumerate() {
+        if let Some(resp) = middleware.before(request) {
+            return (i + 1, Some(resp));
+        }
+    }
+
+    (chain.len(), None)
+}
+
+pub fn run_after(chain: &[&MiddlewareRef], request: &Request, response: &mut Response) {
+    for middleware in chain.iter().rev() {
+        middleware.after(request, response);
+    }
+}
+
+pub fn run<F>(chain: &[&MiddlewareRef], request: &mut Request, handler: F) -> Response
+where
+    F: FnOnce(&Request) -> Response,
+{
+    let (entered, short_circuit) = run_before(chain, request);
+
+    let mut resp = match short_circuit {
+        Some(resp) => resp,
+        None => handler(request),
+    };
+
+    run_after(&chain[..entered], request, &mut resp);
+
+    resp
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::{HttpStatus, Method};
+    use crate::endpoint::Endpoint;
+    use crate::parser::test_request;
+    use crate::response::IntoResponse;
+    use crate::router::RouteGroup;
+    use crate::samovar::Settings;
+    use std::sync::Mutex;
+
+    type Log = Arc<Mutex<Vec<String>>>;
+
+    struct Recorder(&'static str, Log);
+
+    impl Middleware for Recorder {
+        fn before(&self, _request: &mut Request) -> Option<Response> {
+            self.1.lock().unwrap().push(format!("{} in", self.0));
+
+            None
+        }
+
+        fn after(&self, _request: &Request, _response: &mut Response) {
+            self.1.lock().unwrap().push(format!("{} out", self.0));
+        }
+    }
+
+    fn respond(log: &Log, group: RouteGroup, route: Endpoint) -> Response {
+        let mut settings = Settings::default();
+        settings
+            .middleware
+            .push(Arc::new(Recorder("global", log.clone())));
+
+        let endpoint = group
+            .middleware(Recorder("group", log.clone()))
+            .route(route.with_middleware(Recorder("route", log.clone())))
+            .into_endpoints()
+            .remove(0);
+
+        endpoint.respond(&mut test_request("GET", "/g/a", &[], ""), &settings)
+    }
+
+    fn handler(log: &Log) -> Endpoint {
+        let log = log.clone();
+
+        Endpoint::new(
+            "/a".to_string(),
+            move |_: &Request| {
+                log.lock().unwrap().push("handler".to_string());
+
+                "ok"
+            },
+            Method::GET,
+        )
+    }
+
+    fn entries(log: &Log) -> Vec<String> {
+        log.lock().unwrap().clone()
+    }
+
+    #[test]
+    fn runs_outer_layers_first_and_unwinds_in_reverse() {
+        let log = Log::default();
+
+        respond(&log, RouteGroup::new("/g"), handler(&log));
+
+        assert_eq!(
+            entries(&log),
+            vec![
+                "global in",
+                "group in",
+                "route in",
+                "handler",
+                "route out",
+                "group out",
+                "global out"
+            ]
+        );
+    }
+
+    #[test]
+    fn before_hooks_short_circuit_inner_layers() {
+        let log = Log::default();
+
+        let group = RouteGroup::new("/g").middleware(Before(|_: &mut Request| {
+            Some(HttpStatus::Http401Unauthorized.into_response())
+        }));
+
+        let resp = respond(&log, group, handler(&log));
+
+        assert!(matches!(resp.get_status(), HttpStatus::Http401Unauthorized));
+        assert_eq!(entries(&log), vec!["global in", "global out"]);
+    }
+
+    #[test]
+    fn after_hooks_see_the_response() {
+        let chain: Vec<MiddlewareRef> =
+            vec![Arc::new(After(|_: &Request, resp: &mut Response| {
+                resp.set_header("X-Seen".to_string(), "yes".to_string())
+            }))];
+
+        let resp = run(
+            &chain.iter().collect::<Vec<_>>(),
+            &mut test_request("GET", "/", &[], ""),
+            |_| "ok".into_response(),
+        );
+
+        assert_eq!(resp.get_header("X-Seen").as_deref(), Some("yes"));
+    }
+}
@@ -0,0 +1,406 @@
+use crate::common::*;
+use crate::request::Request;
+use crate::response::Response;
+use serde::Serialize;
+use serde_json::{to_value, to_vec, Value};
+
+#[derive(Clone)]
+pub struct QualityItem {
+    pub value: String,
+    pub q: f32,
+}
+
+#[derive(Clone)]
+pub struct MediaRange {
+    pub main: String,
+    pub sub: String,
+    pub q: f32,
+}
+
+impl MediaRange {
+    pub fn matches(&self, mime: &MimeType) -> bool {
+        let mime_str: String = mime.clone().into();
+
+        let mut split = mime_str.splitn(2, '/');
+        let main = split.next().unwrap_or("");
+        let sub = split.next().unwrap_or("");
+
+        match (self.main.as_str(), self.sub.as_str()) {
+            ("*", "*") => true,
+            (m, "*") => m == main,
+            (m, s) => m == main && s == sub,
+        }
+    }
+
+    fn specificity(&self) -> u8 {
+        match (self.main.as_str(), self.sub.as_str()) {
+            ("*", "*") => 0,
+            (_, "*") => 1,
+            _ => 2,
+        }
+    }
+}
+
+pub fn parse_quality_list(header: &str) -> Vec<QualityItem> {
+    header
+        .split(',')
+        .filter_map(|item| {
+            let mut parts = item.split(';');
+
+            let value = parts.next()?.trim().to_lowercase();
+
+            if value.is_empty() {
+                return None;
+            }
+
+            let q = parts
+                .filter_map(|p| {
+                    let (k, v) = p.split_once('=')?;
+
+                    match k.trim().eq_ignore_ascii_case("q") {
+                        true => v.trim().parse::<f32>().ok(),
+                        false => None,
+                    }
+                })
+                .next()
+                .unwrap_or(1.0)
+                .clamp(0.0, 1.0);
+
+            Some(QualityItem { value, q })
+        })
+        .collect()
+}
+
+pub fn parse_accept(header: &str) -> Vec<MediaRange> {
+    parse_quality_list(header)
+        .into_iter()
+        .map(|item| {
+            let mut split = item.value.splitn(2, '/');
+
+            let main = split.next().unwrap_or("*").to_string();
+            let sub = split.next().unwrap_or("*").to_string();
+
+            MediaRange {
+                main,
+                sub,
+                q: item.q,
+            }
+        })
+        .collect()
+}
+
+pub fn quality_of(ranges: &[MediaRange], mime: &MimeType) -> f32 {
+    ranges
+        .iter()
+        .filter(|r| r.matches(mime))
+        .max_by_key(|r| r.specificity())
+        .map(|r| r.q)
+        .unwrap_or(0.0)
+}
+
+pub fn negotiate(accept: Option<String>, offered: &[MimeType]) -> Option<MimeType> {
+    let ranges = match accept {
+        Some(header) if !header.trim().is_empty() => parse_accept(&header),
+        _ => return offered.first().cloned(),
+    };
+
+    let mut best: Option<(&MimeType, f32)> = None;
+
+    for mime in offered {
+        let q = quality_of(&ranges, mime);
+
+        if q <= 0.0 {
+            continue;
+        }
+
+        match best {
+            Some((_, best_q)) if best_q >= q => {}
+            _ => best = Some((mime, q)),
+        }
+    }
+
+    best.map(|(mime, _)| mime.clone())
+}
+
+pub trait Serializer<T> {
+    fn mime_type(&self) -> MimeType;
+    fn serialize(&self, value: &T) -> Result<Vec<u8>, String>;
+}
+
+pub struct JsonSerializer;
+
+impl<T: Serialize> Serializer<T> for JsonSerializer {
+    fn mime_type(&self) -> MimeType {
+        MimeType::ApplicationJson
+    }
+
+    fn serialize(&self, value: &T) -> Result<Vec<u8>, String> {
+        to_vec(value).map_err(|e| e.to_string())
+    }
+}
+
+pub struct CsvSerializer;
+
+impl CsvSerializer {
+    fn escape(field: String) -> String {
+        match field.contains([',', '"', '\n', '\r']) {
+            true => format!("\"{}\"", field.replace('"', "\"\"")),
+            false => field,
+        }
+    }
+
+    fn cell(value: &Value) -> String {
+        match value {
+            Value::Null => String::new(),
+            Value::String(s) => s.clone(),
+            other => other.to_string(),
+        }
+    }
+
+    fn line(cells: Vec<String>) -> String {
+        cells
+            .into_iter()
+            .map(Self::escape)
+            .collect::<Vec<String>>()
+            .join(",")
+    }
+}
+
+impl<T: Serialize> Serializer<T> for CsvSerializer {
+    fn mime_type(&self) -> MimeType {
+        MimeType::TextCsv
+    }
+
+    fn serialize(&self, value: &T) -> Result<Vec<u8>, String> {
+        let rows = match to_value(value).map_err(|e| e.to_string())? {
+            Value::Array(rows) => rows,
+            other => vec![other],
+        };
+
+        let columns: Vec<String> = match rows.first() {
+            Some(Value::Object(first)) => first.keys().cloned().collect(),
+            _ => vec!["value".to_string()],
+        };
+
+        let mut lines = vec![Self::line(columns.clone())];
+
+        for row in rows.iter() {
+            let cells = match row {
+                Value::Object(obj) => columns
+                    .iter()
+                    .map(|c| obj.get(c).map(Self::cell).unwrap_or_default())
+                    .collect(),
+                other => vec![Self::cell(other)],
+            };
+
+            lines.push(Self::line(cells));
+        }
+
+        Ok(format!("{}\r\n", lines.join("\r\n")).into_bytes())
+    }
+}
+
+pub struct HtmlSerializer<T> {
+    render: Box<dyn Fn(&T) -> String>,
+}
+
+impl<T> HtmlSerializer<T> {
+    pub fn new<F: Fn(&T) -> String + 'static>(render: F) -> Self {
+        HtmlSerializer {
+            render: Box::new(render),
+        }
+    }
+}
+
+impl<T> Serializer<T> for HtmlSerializer<T> {
+    fn mime_type(&self) -> MimeType {
+        MimeType::TextHtml
+    }
+
+    fn serialize(&self, value: &T) -> Result<Vec<u8>, String> {
+        Ok((self.render)(value).into_bytes())
+    }
+}
+
+pub struct Negotiated<T> {
+    value: T,
+    serializers: Vec<Box<dyn Serializer<T>>>,
+}
+
+impl<T> Negotiated<T> {
+    pub fn new(value: T) -> Self {
+        Negotiated {
+            value,
+            serializers: vec![],
+        }
+    }
+
+    pub fn with<S: Serializer<T> + 'static>(mut self, serializer: S) -> Self {
+        self.serializers.push(Box::new(serializer));
+
+        self
+    }
+
+    pub fn offered(&self) -> Vec<MimeType> {
+        self.serializers.iter().map(|s| s.mime_type()).collect()
+    }
+
+    pub fn respond(&self, request: &Request) -> Response {
+        let offered = self.offered();
+
+        let mut resp = match negotiate(request.get_header("accept"), &offered) {
+            Some(mime) => {
+                let serializer = self
+                    .serializers
+                    .iter()
+                    .find(|s| s.mime_type() == mime)
+                    .unwrap();
+
+                match serializer.serialize(&self.value) {
+                    Ok(body) => Response::new_bytes(body, mime, HttpStatus::Http200Ok),
+                    Err(e) => Response::new_string(
+                        e,
+                        MimeType::TextPlain,
+                        HttpStatus::Http500InternalServerError,
+                    ),
+                }
+            }
+            None => {
+                let available = offered
+                    .into_iter()
+                    .map(|m| m.into())
+                    .collect::<Vec<String>>()
+                    .join(", ");
+
+                Response::new_string(
+                    format!("Available representations: {}", available),
+                    MimeType::TextPlain,
+                    HttpStatus::Http406NotAcceptable,
+                )
+            }
+        };
+
+        resp.add_vary("Accept");
+
+        resp
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::test_request;
+
+    fn request(headers: &[&str]) -> Request {
+        test_request("GET", "/doc", &[&["Host: localhost"], headers].concat(), "")
+    }
+
+    fn negotiated(accept: &str) -> Option<String> {
+        let offered = [
+            MimeType::ApplicationJson,
+            MimeType::TextHtml,
+            MimeType::TextCsv,
+        ];
+
+        negotiate(Some(accept.to_string()), &offered).map(|m| m.into())
+    }
+
+    fn body(mut resp: Response) -> String {
+        let raw = String::from_utf8(resp.compose().into_bytes()).unwrap();
+
+        raw.split_once("\r\n\r\n").unwrap().1.to_string()
+    }
+
+    #[test]
+    fn parses_media_ranges_and_quality() {
+        let ranges = parse_accept("Text/HTML;level=1;q=0.5, */*; q=0.1, application/*, bad;q=x, ");
+
+        assert_eq!(ranges.len(), 4);
+        assert_eq!(
+            (ranges[0].main.as_str(), ranges[0].sub.as_str()),
+            ("text", "html")
+        );
+        assert_eq!(ranges[0].q, 0.5);
+        assert_eq!(
+            (ranges[1].main.as_str(), ranges[1].sub.as_str()),
+            ("*", "*")
+        );
+        assert_eq!(ranges[1].q, 0.1);
+        assert_eq!(ranges[2].q, 1.0);
+        assert_eq!(
+            (ranges[3].main.as_str(), ranges[3].sub.as_str()),
+            ("bad", "*")
+        );
+        assert_eq!(ranges[3].q, 1.0);
+
+        assert_eq!(parse_quality_list("gzip;q=7")[0].q, 1.0);
+    }
+
+    #[test]
+    fn most_specific_range_sets_the_quality() {
+        let ranges = parse_accept("*/*;q=0.2, text/*;q=0.5, text/html");
+
+        assert_eq!(quality_of(&ranges, &MimeType::TextHtml), 1.0);
+        assert_eq!(quality_of(&ranges, &MimeType::TextCsv), 0.5);
+        assert_eq!(quality_of(&ranges, &MimeType::ApplicationJson), 0.2);
+        assert_eq!(
+            quality_of(&parse_accept("text/html"), &MimeType::TextCsv),
+            0.0
+        );
+    }
+
+    #[test]
+    fn negotiates_by_quality_then_server_order() {
+        assert_eq!(negotiated("text/html").as_deref(), Some("text/html"));
+        assert_eq!(
+            negotiated("application/json;q=0.4, text/csv;q=0.9").as_deref(),
+            Some("text/csv")
+        );
+        assert_eq!(negotiated("text/*").as_deref(), Some("text/html"));
+        assert_eq!(negotiated("*/*").as_deref(), Some("application/json"));
+        assert_eq!(
+            negotiated("*/*, application/json;q=0").as_deref(),
+            Some("text/html")
+        );
+        assert_eq!(negotiated("image/png"), None);
+        assert!(negotiate(None, &[MimeType::TextCsv]) == Some(MimeType::TextCsv));
+        assert!(
+            negotiate(Some(" ".to_string()), &[MimeType::TextHtml]) == Some(MimeType::TextHtml)
+        );
+    }
+
+    #[test]
+    fn responds_with_the_chosen_serializer() {
+        let rows = vec![serde_json::json!({"a": 1, "b": "x,y"})];
+        let negotiated = Negotiated::new(rows)
+            .with(JsonSerializer)
+            .with(CsvSerializer)
+            .with(HtmlSerializer::new(|rows: &Vec<Value>| {
+                format!("<p>{}</p>", rows.len())
+            }));
+
+        let resp = negotiated.respond(&request(&["Accept: text/csv"]));
+        assert_eq!(resp.get_header("Vary").as_deref(), Some("Accept"));
+        assert_eq!(body(resp), "a,b\r\n1,\"x,y\"\r\n");
+
+        let resp = negotiated.respond(&request(&["Accept: text/html, */*;q=0.1"]));
+        assert_eq!(body(resp), "<p>1</p>");
+
+        let resp = negotiated.respond(&request(&[]));
+        assert_eq!(body(resp), "[{\"a\":1,\"b\":\"x,y\"}]");
+    }
+
+    #[test]
+    fn answers_406_when_nothing_is_acceptable() {
+        let negotiated = Negotiated::new(1u8).with(JsonSerializer);
+
+        let resp = negotiated.respond(&request(&["Accept: text/html"]));
+
+        assert!(matches!(
+            resp.get_status(),
+            HttpStatus::Http406NotAcceptable
+        ));
+        assert_eq!(resp.get_header("Vary").as_deref(), Some("Accept"));
+        assert_eq!(body(resp), "Available representations: application/json");
+    }
+}
@@ -0,0 +1,731 @@
+use crate::common::*;
+use crate::endpoint::Endpoint;
+use crate::guard::Guard;
+use crate::router::{RoutePattern, Segment};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, TimeZone};
+use serde_json::{json, Map, Value};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+pub trait Schema {
+    fn schema_name() -> String;
+    fn schema() -> Value;
+
+    fn required() -> bool {
+        true
+    }
+}
+
+macro_rules! primitive_schema {
+    ($($t:ty => $name:expr, $schema:expr;)*) => {
+        $(
+            impl Schema for $t {
+                fn schema_name() -> String {
+                    $name.to_string()
+                }
+
+                fn schema() -> Value {
+                    $schema
+                }
+            }
+        )*
+    };
+}
+
+primitive_schema! {
+    String => "string", json!({"type": "string"});
+    &str => "string", json!({"type": "string"});
+    char => "string", json!({"type": "string", "minLength": 1, "maxLength": 1});
+    bool => "boolean", json!({"type": "boolean"});
+    i8 => "integer", json!({"type": "integer", "format": "int32"});
+    i16 => "integer", json!({"type": "integer", "format": "int32"});
+    i32 => "integer", json!({"type": "integer", "format": "int32"});
+    i64 => "integer", json!({"type": "integer", "format": "int64"});
+    isize => "integer", json!({"type": "integer", "format": "int64"});
+    u8 => "integer", json!({"type": "integer", "format": "int32", "minimum": 0});
+    u16 => "integer", json!({"type": "integer", "format": "int32", "minimum": 0});
+    u32 => "integer", json!({"type": "integer", "format": "int64", "minimum": 0});
+    u64 => "integer", json!({"type": "integer", "format": "int64", "minimum": 0});
+    usize => "integer", json!({"type": "integer", "format": "int64", "minimum": 0});
+    i128 => "integer", json!({"type": "integer"});
+    u128 => "integer", json!({"type": "integer", "minimum": 0});
+    f32 => "number", json!({"type": "number", "format": "float"});
+    f64 => "number", json!({"type": "number", "format": "double"});
+    Value => "any", json!({});
+    () => "null", json!({"type": "null"});
+    NaiveDate => "date", json!({"type": "string", "format": "date"});
+    NaiveDateTime => "datetime", json!({"type": "string", "format": "date-time"});
+}
+
+impl<Tz: TimeZone> Schema for DateTime<Tz> {
+    fn schema_name() -> String {
+        "datetime".to_string()
+    }
+
+    fn schema() -> Value {
+        json!({"type": "string", "format": "date-time"})
+    }
+}
+
+macro_rules! set_schema {
+    ($($set:ident),*) => {
+        $(
+            impl<T: Schema> Schema for $set<T> {
+                fn schema_name() -> String {
+                    format!("{}Set", T::schema_name())
+                }
+
+                fn schema() -> Value {
+                    json!({"type": "array", "items": T::schema(), "uniqueItems": true})
+                }
+            }
+        )*
+    };
+}
+
+set_schema!(HashSet, BTreeSet);
+
+macro_rules! tuple_schema {
+    ($(($($t:ident),+))*) => {
+        $(
+            impl<$($t: Schema),+> Schema for ($($t,)+) {
+                fn schema_name() -> String {
+                    let mut name = "Tuple".to_string();
+
+                    $(name.push_str(&$t::schema_name());)+
+
+                    name
+                }
+
+                fn schema() -> Value {
+                    let items = vec![$($t::schema()),+];
+                    let len = items.len();
+
+                    json!({"type": "array", "prefixItems": items, "minItems": len, "maxItems": len})
+                }
+            }
+        )*
+    };
+}
+
+tuple_schema! {
+    (A)
+    (A, B)
+    (A, B, C)
+    (A, B, C, D)
+    (A, B, C, D, E)
+    (A, B, C, D, E, F)
+}
+
+impl<T: Schema> Schema for Option<T> {
+    fn schema_name() -> String {
+        T::schema_name()
+    }
+
+    fn schema() -> Value {
+        let mut schema = T::schema();
+
+        if let Some(Value::String(t)) = schema.get("type").cloned() {
+            schema["type"] = json!([t, "null"]);
+        }
+
+        schema
+    }
+
+    fn required() -> bool {
+        false
+    }
+}
+
+impl<T: Schema> Schema for Vec<T> {
+    fn schema_name() -> String {
+        format!("{}List", T::schema_name())
+    }
+
+    fn schema() -> Value {
+        json!({"type": "array", "items": T::schema()})
+    }
+}
+
+impl<T: Schema> Schema for Box<T> {
+    fn schema_name() -> String {
+        T::schema_name()
+    }
+
+    fn schema() -> Value {
+        T::schema()
+    }
+}
+
+impl<T: Schema> Schema for HashMap<String, T> {
+    fn schema_name() -> String {
+        format!("{}Map", T::schema_name())
+    }
+
+    fn schema() -> Value {
+        json!({"type": "object", "additionalProperties": T::schema()})
+    }
+}
+
+impl<T: Schema> Schema for BTreeMap<String, T> {
+    fn schema_name() -> String {
+        format!("{}Map", T::schema_name())
+    }
+
+    fn schema() -> Value {
+        json!({"type": "object", "additionalProperties": T::schema()})
+    }
+}
+
+#[derive(Clone)]
+pub struct SchemaRef {
+    pub name: String,
+    pub schema: Value,
+}
+
+impl SchemaRef {
+    pub fn of<T: Schema>() -> Self {
+        SchemaRef {
+            name: T::schema_name(),
+            schema: T::schema(),
+        }
+    }
+
+    fn reference(&self, components: &mut Map<String, Value>) -> Value {
+        match self.schema.get("type").and_then(|t| t.as_str()) {
+            Some("object") => {
+                components.insert(self.name.clone(), self.schema.clone());
+
+                json!({"$ref": format!("#/components/schemas/{}", self.name)})
+            }
+            _ => self.schema.clone(),
+        }
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct EndpointDocs {
+    pub summary: Option<String>,
+    pub request: Option<SchemaRef>,
+    pub response: Option<SchemaRef>,
+    pub hidden: bool,
+}
+
+#[derive(Clone)]
+pub struct OpenApiConfig {
+    title: String,
+    version: String,
+    description: Option<String>,
+    path: String,
+}
+
+impl Default for OpenApiConfig {
+    fn default() -> Self {
+        OpenApiConfig {
+            title: "Samovar API".to_string(),
+            version: "0.1.0".to_string(),
+            description: None,
+            path: "/docs".to_string(),
+        }
+    }
+}
+
+impl OpenApiConfig {
+    pub fn new(title: &str, version: &str) -> Self {
+        OpenApiConfig {
+            title: title.to_string(),
+            version: version.to_string(),
+            ..Self::default()
+        }
+    }
+
+    pub fn description(mut self, description: &str) -> Self {
+        self.description = Some(description.to_string());
+
+        self
+    }
+
+    pub fn path(mut self, path: &str) -> Self {
+        self.path = path.to_string();
+
+        self
+    }
+
+    pub fn get_path(&self) -> String {
+        self.path.clone()
+    }
+
+    pub fn get_title(&self) -> String {
+        self.title.clone()
+    }
+}
+
+fn openapi_path(pattern: &RoutePattern) -> String {
+    let segments = pattern
+        .get_segments()
+        .into_iter()
+        .map(|s| match s {
+            Segment::Static(s) => s,
+            Segment::Param(name) | Segment::Wildcard(name) => format!("{{{}}}", name),
+        })
+        .collect::<Vec<String>>();
+
+    format!("/{}", segments.join("/"))
+}
+
+fn operation_id(endpoint: &Endpoint) -> String {
+    match endpoint.get_name() {
+        Some(name) => name,
+        None => {
+            let method_str: String = endpoint.get_method().into();
+
+            let path = crate::router::split_path(&endpoint.get_uri())
+                .into_iter()
+                .map(|s| s.trim_start_matches([':', '*']))
+                .collect::<Vec<&str>>()
+                .join("_");
+
+            format!("{}_{}", method_str.to_lowercase(), path)
+        }
+    }
+}
+
+fn parameters(endpoint: &Endpoint, pattern: &RoutePattern) -> Vec<Value> {
+    let mut ret: Vec<Value> = vec![];
+
+    for segment in pattern.get_segments() {
+        match segment {
+            Segment::Param(name) | Segment::Wildcard(name) => ret.push(json!({
+                "name": name,
+                "in": "path",
+                "required": true,
+                "schema": {"type": "string"}
+            })),
+            Segment::Static(_) => {}
+        }
+    }
+
+    for guard in endpoint.get_guards() {
+        match guard {
+            Guard::QueryParam(name) => ret.push(json!({
+                "name": name,
+                "in": "query",
+                "required": true,
+                "schema": {"type": "string"}
+            })),
+            Guard::Header(name, value) => ret.push(json!({
+                "name": name,
+                "in": "header",
+                "required": true,
+                "schema": {"type": "string", "enum": [value]}
+            })),
+            Guard::HeaderRegex(name, regex) => ret.push(json!({
+                "name": name,
+                "in": "header",
+                "required": true,
+                "schema": {"type": "string", "pattern": regex.as_str()}
+            })),
+            _ => {}
+        }
+    }
+
+    ret
+}
+
+fn request_content_type(endpoint: &Endpoint) -> String {
+    endpoint
+        .get_guards()
+        .into_iter()
+        .find_map(|g| match g {
+            Guard::ContentType(mime) => Some(mime.into()),
+            _ => None,
+        })
+        .unwrap_or_else(|| MimeType::ApplicationJson.into())
+}
+
+fn operation(
+    endpoint: &Endpoint,
+    pattern: &RoutePattern,
+    components: &mut Map<String, Value>,
+) -> Value {
+    let docs = endpoint.get_docs();
+
+    let mut op = Map::new();
+
+    op.insert("operationId".to_string(), json!(operation_id(endpoint)));
+
+    if let Some(summary) = docs.summary {
+        op.insert("summary".to_string(), json!(summary));
+    }
+
+    let params = parameters(endpoint, pattern);
+
+    if !params.is_empty() {
+        op.insert("parameters".to_string(), json!(params));
+    }
+
+    if let Some(request) = docs.request {
+        let mut content = Map::new();
+        content.insert(
+            request_content_type(endpoint),
+            json!({"schema": request.reference(components)}),
+        );
+
+        op.insert(
+            "requestBody".to_string(),
+            json!({"required": true, "content": content}),
+        );
+    }
+
+    let ok = match docs.response {
+        Some(response) => json!({
+            "description": "OK",
+            "content": {"application/json": {"schema": response.reference(components)}}
+        }),
+        None => json!({"description": "OK"}),
+    };
+
+    op.insert("responses".to_string(), json!({"200": ok}));
+
+    Value::Object(op)
+}
+
+pub fn generate(config: &OpenApiConfig, endpoints: &[std::sync::Arc<Endpoint>]) -> Value {
+    let mut paths: BTreeMap<String, Map<String, Value>> = BTreeMap::new();
+    let mut components = Map::new();
+
+    for endpoint in endpoints.iter() {
+        if endpoint.get_docs().hidden {
+            continue;
+        }
+
+        let pattern = RoutePattern::parse(&endpoint.get_uri());
+        let method_str: String = endpoint.get_method().into();
+
+        let method_key = match method_str.as_str() {
+            "OPTION" => "options".to_string(),
+            m => m.to_lowercase(),
+        };
+
+        paths
+            .entry(openapi_path(&pattern))
+            .or_default()
+            .insert(method_key, operation(endpoint, &pattern, &mut components));
+    }
+
+    let mut info = Map::new();
+    info.insert("title".to_string(), json!(config.title));
+    info.insert("version".to_string(), json!(config.version));
+
+    if let Some(description) = &config.description {
+        info.insert("description".to_string(), json!(description));
+    }
+
+    json!({
+        "openapi": "3.1.0",
+        "info": info,
+        "paths": paths,
+        "components": {"schemas": components}
+    })
+}
+
+pub fn to_json(doc: &Value) -> String {
+    serde_json::to_string_pretty(doc).unwrap()
+}
+
+fn yaml_key(key: &str) -> String {
+    let plain = !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        && !key.starts_with('-');
+
+    match plain {
+        true => key.to_string(),
+        false => Value::String(key.to_string()).to_string(),
+    }
+}
+
+fn yaml_scalar(value: &Value) -> String {
+    match value {
+        Value::Null => "null".to_string(),
+        Value::Object(o) if o.is_empty() => "{}".to_string(),
+        Value::Array(a) if a.is_empty() => "[]".to_string(),
+        other => other.to_string(),
+    }
+}
+
+fn is_nested(value: &Value) -> bool {
+    match value {
+        Value::Object(o) => !o.is_empty(),
+        Value::Array(a) => !a.is_empty(),
+        _ => false,
+    }
+}
+
+fn write_yaml(value: &Value, indent: usize, out: &mut String) {
+    let pad = "  ".repeat(indent);
+
+    match value {
+        Value::Object(o) if !o.is_empty() => {
+            for (k, v) in o.iter() {
+                match is_nested(v) {
+                    true => {
+                        out.push_str(&format!("{}{}:\n", pad, yaml_key(k)));
+                        write_yaml(v, indent + 1, out);
+                    }
+                    false => out.push_str(&format!("{}{}: {}\n", pad, yaml_key(k), yaml_scalar(v))),
+                }
+            }
+        }
+        Value::Array(a) if !a.is_empty() => {
+            for v in a.iter() {
+                match is_nested(v) {
+                    true => {
+                        let mut nested = String::new();
+                        write_yaml(v, indent + 1, &mut nested);
+
+                        let trimmed = nested.trim_start_matches(' ');
+                        out.push_str(&format!("{}- {}", pad, trimmed));
+                    }
+                    false => out.push_str(&format!("{}- {}\n", pad, yaml_scalar(v))),
+                }
+            }
+        }
+        other => out.push_str(&format!("{}{}\n", pad, yaml_scalar(other))),
+    }
+}
+
+pub fn to_yaml(doc: &Value) -> String {
+    let mut out = String::new();
+
+    write_yaml(doc, 0, &mut out);
+
+    out
+}
+
+fn html_escape(s: &str) -> String {
+    s.chars().fold(String::new(), |mut out, c| {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+
+        out
+    })
+}
+
+fn js_string(s: &str) -> String {
+    Value::String(s.to_string())
+        .to_string()
+        .replace('<', "\\u003c")
+        .replace('>', "\\u003e")
+        .replace('&', "\\u0026")
+        .replace('\u{2028}', "\\u2028")
+        .replace('\u{2029}', "\\u2029")
+}
+
+pub fn docs_page(config: &OpenApiConfig, spec_url: &str) -> String {
+    let (head, tail) = DOCS_PAGE.split_once("REPLACE_SPEC").unwrap();
+
+    format!(
+        "{}{}{}",
+        head.replace("REPLACE_TITLE", &html_escape(&config.title)),
+        js_string(spec_url),
+        tail
+    )
+}
+
+const DOCS_PAGE: &str = r#"<!DOCTYPE html><html><head><meta charset="utf-8"><title>REPLACE_TITLE</title><style>body{font-family:sans-serif;margin:2em auto;max-width:60em;color:#222}h1{border-bottom:1px solid #ccc}.op{border:1px solid #ddd;border-radius:4px;margin:.5em 0}.op summary{padding:.5em;cursor:pointer}.m{display:inline-block;width:5em;font-weight:bold;text-transform:uppercase}.get{color:#0a7}.post{color:#07c}.put{color:#c70}.delete{color:#c22}.body{padding:0 1em 1em}pre{background:#f6f6f6;padding:.5em;overflow:auto}table{border-collapse:collapse}td,th{border:1px solid #ddd;padding:.2em .5em;text-align:left}</style></head><body><h1>REPLACE_TITLE</h1><div id="ops">Loading...</div><script>
+fetch(REPLACE_SPEC).then(function(r){return r.json()}).then(function(spec){
+var ops=document.getElementById("ops");ops.innerHTML="";
+var esc=function(s){return String(s).replace(/[&<>"]/g,function(c){return{"&":"&amp;","<":"&lt;",">":"&gt;",'"':"&quot;"}[c]})};
+var schemas=(spec.components||{}).schemas||{};
+var show=function(s){if(s&&s["$ref"]){var n=s["$ref"].split("/").pop();return n+"\n"+JSON.stringify(schemas[n],null,2)}return JSON.stringify(s,null,2)};
+if(spec.info.description){var d=document.createElement("p");d.textContent=spec.info.description;ops.appendChild(d)}
+Object.keys(spec.paths).forEach(function(path){var item=spec.paths[path];Object.keys(item).forEach(function(method){var op=item[method];
+var h='<details class="op"><summary><span class="m '+method+'">'+method+'</span><code>'+esc(path)+'</code> '+esc(op.summary||"")+'</summary><div class="body">';
+if(op.parameters){h+='<h4>Parameters</h4><table><tr><th>Name</th><th>In</th><th>Schema</th></tr>';op.parameters.forEach(function(p){h+='<tr><td>'+esc(p.name)+'</td><td>'+esc(p.in)+'</td><td><code>'+esc(JSON.stringify(p.schema))+'</code></td></tr>'});h+='</table>'}
+if(op.requestBody){Object.keys(op.requestBody.content).forEach(function(ct){h+='<h4>Request body ('+esc(ct)+')</h4><pre>'+esc(show(op.requestBody.content[ct].schema))+'</pre>'})}
+Object.keys(op.responses).forEach(function(code){var r=op.responses[code];h+='<h4>Response '+esc(code)+'</h4>';if(r.content){Object.keys(r.content).forEach(function(ct){h+='<pre>'+esc(show(r.content[ct].schema))+'</pre>'})}});
+ops.insertAdjacentHTML("beforeend",h+'</div></details>')})})
+}).catch(function(e){document.getElementById("ops").textContent="Could not load spec: "+e});
+</script></body></html>"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::request::Request;
+    use std::sync::Arc;
+
+    struct User;
+
+    impl Schema for User {
+        fn schema_name() -> String {
+            "User".to_string()
+        }
+
+        fn schema() -> Value {
+            json!({
+                "type": "object",
+                "properties": {"id": u64::schema(), "nick": Option::<String>::schema()},
+                "required": ["id"]
+            })
+        }
+    }
+
+    fn endpoint(uri: &str, method: Method) -> Endpoint {
+        Endpoint::new(uri.to_string(), |_: &Request| "", method)
+    }
+
+    fn document() -> Value {
+        let endpoints = vec![
+            endpoint("/users/:id", Method::GET)
+                .with_name("user")
+                .with_summary("Fetch a user")
+                .with_response_schema::<User>(),
+            endpoint("/users", Method::POST)
+                .with_guard(Guard::content_type(MimeType::from(
+                    "application/x-www-form-urlencoded",
+                )))
+                .with_request_schema::<User>(),
+            endpoint("/files/*path", Method::GET)
+                .with_guard(Guard::query_param("v"))
+                .with_guard(Guard::header("X-Version", "2"))
+                .with_response_schema::<Vec<String>>(),
+            endpoint("/internal", Method::GET).hidden(),
+        ];
+
+        let config = OpenApiConfig::new("Test", "1.2.3").description("Docs");
+
+        generate(
+            &config,
+            &endpoints.into_iter().map(Arc::new).collect::<Vec<_>>(),
+        )
+    }
+
+    #[test]
+    fn primitive_and_container_schemas() {
+        assert_eq!(Option::<i64>::schema()["type"], json!(["integer", "null"]));
+        assert!(!Option::<i64>::required());
+        assert_eq!(Vec::<String>::schema_name(), "stringList");
+        assert_eq!(HashSet::<u8>::schema()["uniqueItems"], json!(true));
+        assert_eq!(<(u8, String)>::schema()["maxItems"], json!(2));
+        assert_eq!(u128::schema()["type"], json!("integer"));
+        assert_eq!(NaiveDate::schema()["format"], json!("date"));
+    }
+
+    #[test]
+    fn documents_paths_and_operations() {
+        let doc = document();
+
+        assert_eq!(doc["openapi"], json!("3.1.0"));
+        assert_eq!(doc["info"]["title"], json!("Test"));
+        assert_eq!(doc["info"]["description"], json!("Docs"));
+        assert!(doc["paths"].get("/internal").is_none());
+
+        let get_user = &doc["paths"]["/users/{id}"]["get"];
+
+        assert_eq!(get_user["operationId"], json!("user"));
+        assert_eq!(get_user["summary"], json!("Fetch a user"));
+        assert_eq!(get_user["parameters"][0]["name"], json!("id"));
+        assert_eq!(get_user["parameters"][0]["in"], json!("path"));
+        assert_eq!(
+            get_user["responses"]["200"]["content"]["application/json"]["schema"],
+            json!({"$ref": "#/components/schemas/User"})
+        );
+
+        let post_users = &doc["paths"]["/users"]["post"];
+
+        assert_eq!(post_users["operationId"], json!("post_users"));
+        assert_eq!(
+            post_users["requestBody"]["content"]["application/x-www-form-urlencoded"]["schema"]
+                ["$ref"],
+            json!("#/components/schemas/User")
+        );
+        assert_eq!(post_users["responses"]["200"], json!({"description": "OK"}));
+
+        assert_eq!(doc["components"]["schemas"]["User"], User::schema());
+    }
+
+    #[test]
+    fn documents_guards_and_inline_schemas() {
+        let doc = document();
+        let files = &doc["paths"]["/files/{path}"]["get"];
+
+        assert_eq!(files["operationId"], json!("get_files_path"));
+        assert_eq!(
+            files["parameters"],
+            json!([
+                {"name": "path", "in": "path", "required": true, "schema": {"type": "string"}},
+                {"name": "v", "in": "query", "required": true, "schema": {"type": "string"}},
+                {
+                    "name": "x-version",
+                    "in": "header",
+                    "required": true,
+                    "schema": {"type": "string", "enum": ["2"]}
+                }
+            ])
+        );
+        assert_eq!(
+            files["responses"]["200"]["content"]["application/json"]["schema"],
+            json!({"type": "array", "items": {"type": "string"}})
+        );
+        assert!(doc["components"]["schemas"].get("stringList").is_none());
+    }
+
+    #[test]
+    fn writes_yaml() {
+        let doc = json!({
+            "openapi": "3.1.0",
+            "paths": {
+                "/users/{id}": {
+                    "get": {
+                        "parameters": [
+                            {"name": "id", "required": true},
+                            {"name": "q", "schema": {}}
+                        ],
+                        "tags": ["a", "b: c"]
+                    }
+                }
+            },
+            "empty": [],
+            "nothing": null
+        });
+
+        let expected = concat!(
+            "empty: []\n",
+            "nothing: null\n",
+            "openapi: \"3.1.0\"\n",
+            "paths:\n",
+            "  \"/users/{id}\":\n",
+            "    get:\n",
+            "      parameters:\n",
+            "        - name: \"id\"\n",
+            "          required: true\n",
+            "        - name: \"q\"\n",
+            "          schema: {}\n",
+            "      tags:\n",
+            "        - \"a\"\n",
+            "        - \"b: c\"\n",
+        );
+
+        assert_eq!(to_yaml(&doc), expected);
+    }
+
+    #[test]
+    fn docs_page_escapes_title_and_spec_url() {
+        let config = OpenApiConfig::new("<script>alert('x')</script> & co", "1");
+        let page = docs_page(&config, "/spec\"</script><script>alert(1)//");
+
+        assert!(!page.contains("<script>alert"));
+        assert!(page
+            .contains("<title>&lt;script&gt;alert(&#39;x&#39;)&lt;/script&gt; &amp; co</title>"));
+        assert!(page
+            .contains("fetch(\"/spec\\\"\\u003c/script\\u003e\\u003cscript\\u003ealert(1)//\")"));
+        assert_eq!(page.matches("</script>").count(), 1);
+    }
+}
@@ -0,0 +1,343 @@
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+pub const DEFAULT_WORKERS: usize = 16;
+pub const DEFAULT_QUEUE_SIZE: usize = 1024;
+pub const DEFAULT_RETRY_AFTER: u64 = 1;
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+#[derive(Clone, Debug)]
+pub struct PoolConfig {
+    workers: usize,
+    queue_size: usize,
+    retry_after: u64,
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        PoolConfig {
+            workers: DEFAULT_WORKERS,
+            queue_size: DEFAULT_QUEUE_SIZE,
+            retry_after: DEFAULT_RETRY_AFTER,
+        }
+    }
+}
+
+impl PoolConfig {
+    pub fn new() -> Self {
+        PoolConfig::default()
+    }
+
+    pub fn workers(mut self, workers: usize) -> Self {
+        self.workers = workers.max(1);
+
+        self
+    }
+
+    pub fn queue_size(mut self, queue_size: usize) -> Self {
+        self.queue_size = queue_size;
+
+        self
+    }
+
+    pub fn retry_after(mut self, seconds: u64) -> Self {
+        self.retry_after = seconds;
+
+        self
+    }
+
+    pub fn get_workers(&self) -> usize {
+        self.workers
+    }
+
+    pub fn get_queue_size(&self) -> usize {
+        self.queue_size
+    }
+
+    pub fn get_retry_after(&self) -> u64 {
+        self.retry_after
+    }
+}
+
+#[derive(Default, Debug)]
+pub struct PoolStats {
+    queued: AtomicUsize,
+    active: AtomicUsize,
+    rejected: AtomicUsize,
+}
+
+impl PoolStats {
+    pub fn queue_depth(&self) -> usize {
+        self.queued.load(Ordering::SeqCst)
+    }
+
+    pub fn active(&self) -> usize {
+        self.active.load(Ordering::SeqCst)
+    }
+
+    pub fn rejected(&self) -> usize {
+        self.rejected.load(Ordering::SeqCst)
+    }
+
+    pub(crate) fn begin(&self) {
+        self.active.fetch_add(1, Ordering::SeqCst);
+    }
+
+    pub(crate) fn end(&self) {
+        self.active.fetch_sub(1, Ordering::SeqCst);
+    }
+
+    pub(crate) fn reject(&self) {
+        self.rejected.fetch_add(1, Ordering::SeqCst);
+    }
+
+    pub(crate) fn try_enqueue(&self, config: &PoolConfig) -> bool {
+        let idle = config.workers.saturating_sub(self.active());
+        let capacity = config.queue_size + idle;
+
+        let reserved = self
+            .queued
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |queued| {
+                match queued < capacity {
+                    true => Some(queued + 1),
+                    false => None,
+                }
+            });
+
+        if reserved.is_err() {
+            self.reject();
+        }
+
+        reserved.is_ok()
+    }
+
+    pub(crate) fn dequeue(&self) {
+        self.queued.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+pub struct ThreadPool {
+    config: PoolConfig,
+    sender: Mutex<Option<Sender<Job>>>,
+    workers: Vec<JoinHandle<()>>,
+    stats: Arc<PoolStats>,
+}
+
+impl ThreadPool {
+    pub fn new(config: PoolConfig) -> Self {
+        Self::with_stats(config, Arc::new(PoolStats::default()))
+    }
+
+    pub fn with_stats(config: PoolConfig, stats: Arc<PoolStats>) -> Self {
+        let (sender, receiver) = channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+
+        let workers = (0..config.workers)
+            .map(|i| Self::spawn_worker(i, receiver.clone(), stats.clone()))
+            .collect();
+
+        ThreadPool {
+            config,
+            sender: Mutex::new(Some(sender)),
+            workers,
+            stats,
+        }
+    }
+
+    fn spawn_worker(
+        id: usize,
+        receiver: Arc<Mutex<Receiver<Job>>>,
+        stats: Arc<PoolStats>,
+    ) -> JoinHandle<()> {
+        thread::Builder::new()
+            .name(format!("samovar-worker-{}", id))
+            .spawn(move || loop {
+                let job = match receiver.lock().unwrap().recv() {
+                    Ok(job) => job,
+                    Err(_) => break,
+                };
+
+                stats.begin();
+                stats.dequeue();
+
+                if panic::catch_unwind(AssertUnwindSafe(job)).is_err() {
+                    println!("Worker {} recovered from a panicking job", id);
+                }
+
+                stats.end();
+            })
+            .unwrap()
+    }
+
+    pub fn reserve(&self) -> Option<QueueSlot<'_>> {
+        match self.stats.try_enqueue(&self.config) {
+            true => Some(QueueSlot {
+                pool: self,
+                used: false,
+            }),
+            false => None,
+        }
+    }
+
+    pub fn execute<F: FnOnce() + Send + 'static>(&self, job: F) -> bool {
+        match self.reserve() {
+            Some(slot) => {
+                slot.execute(job);
+
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn get_config(&self) -> PoolConfig {
+        self.config.clone()
+    }
+
+    pub fn get_stats(&self) -> Arc<PoolStats> {
+        self.stats.clone()
+    }
+
+    pub fn queue_depth(&self) -> usize {
+        self.stats.queue_depth()
+    }
+}
+
+impl Drop for ThreadPool {
+    fn drop(&mut self) {
+        self.sender.lock().unwrap().take();
+
+        for worker in self.workers.drain(..) {
+            worker.join().ok();
+        }
+    }
+}
+
+pub struct QueueSlot<'a> {
+    pool: &'a ThreadPool,
+    used: bool,
+}
+
+impl<'a> QueueSlot<'a> {
+    pub fn execute<F: FnOnce() + Send + 'static>(mut self, job: F) {
+        let sender = self.pool.sender.lock().unwrap();
+
+        let sent = match sender.as_ref() {
+            Some(sender) => sender.send(Box::new(job)).is_ok(),
+            None => false,
+        };
+
+        self.used = sent;
+    }
+}
+
+impl<'a> Drop for QueueSlot<'a> {
+    fn drop(&mut self) {
+        if !self.used {
+            self.pool.stats.dequeue();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc::sync_channel;
+    use std::time::Duration;
+
+    fn blocked_pool(workers: usize, queue_size: usize) -> (ThreadPool, Vec<Sender<()>>) {
+        let pool = ThreadPool::new(PoolConfig::new().workers(workers).queue_size(queue_size));
+        let (started, wait) = sync_channel(workers);
+
+        let releases = (0..workers)
+            .map(|_| {
+                let (release, blocked) = channel::<()>();
+                let started = started.clone();
+
+                assert!(pool.execute(move || {
+                    started.send(()).unwrap();
+                    blocked.recv().ok();
+                }));
+
+                release
+            })
+            .collect::<Vec<_>>();
+
+        for _ in 0..workers {
+            wait.recv_timeout(Duration::from_secs(5)).unwrap();
+        }
+
+        (pool, releases)
+    }
+
+    #[test]
+    fn sheds_jobs_once_workers_and_queue_are_full() {
+        let (pool, releases) = blocked_pool(2, 1);
+
+        assert_eq!(pool.get_stats().active(), 2);
+        assert!(pool.execute(|| ()));
+        assert_eq!(pool.queue_depth(), 1);
+
+        assert!(!pool.execute(|| ()));
+        assert_eq!(pool.get_stats().rejected(), 1);
+
+        drop(releases);
+    }
+
+    #[test]
+    fn unused_slots_are_released() {
+        let (pool, releases) = blocked_pool(1, 1);
+
+        let slot = pool.reserve();
+        assert!(slot.is_some());
+        assert!(pool.reserve().is_none());
+
+        drop(slot);
+        assert_eq!(pool.queue_depth(), 0);
+        assert!(pool.reserve().is_some());
+
+        drop(releases);
+    }
+
+    #[test]
+    fn panicking_jobs_keep_the_counters_balanced() {
+        let pool = ThreadPool::new(PoolConfig::new().workers(1).queue_size(0));
+        let (done, finished) = channel();
+
+        assert!(pool.execute(|| panic!("job failed")));
+
+        while !pool.execute({
+            let done = done.clone();
+            move || done.send(()).unwrap()
+        }) {
+            thread::sleep(Duration::from_millis(5));
+        }
+
+        finished.recv_timeout(Duration::from_secs(5)).unwrap();
+        drop(pool);
+    }
+
+    #[test]
+    fn enqueue_counts_waiters_until_dequeued() {
+        let stats = PoolStats::default();
+        let config = PoolConfig::new().workers(1).queue_size(1);
+
+        assert!(stats.try_enqueue(&config));
+        assert!(stats.try_enqueue(&config));
+        assert_eq!(stats.queue_depth(), 2);
+
+        assert!(!stats.try_enqueue(&config));
+        assert_eq!(stats.rejected(), 1);
+
+        stats.dequeue();
+        stats.begin();
+        assert!(!stats.try_enqueue(&config));
+
+        stats.dequeue();
+        assert!(stats.try_enqueue(&config));
+    }
+}
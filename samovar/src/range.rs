@@ -0,0 +1,271 @@
+use crate::conditional::{parse_http_date, ETag};
+use crate::request::Request;
+use chrono::{DateTime, Utc};
+
+pub const MAX_RANGES: usize = 32;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct ByteRange {
+    pub start: u64,
+    pub end: u64,
+}
+
+impl ByteRange {
+    pub fn len(&self) -> u64 {
+        self.end - self.start + 1
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.end < self.start
+    }
+
+    pub fn content_range(&self, total: u64) -> String {
+        format!("bytes {}-{}/{}", self.start, self.end, total)
+    }
+}
+
+pub enum RangeResult {
+    Ignored,
+    Satisfiable(Vec<ByteRange>),
+    Unsatisfiable,
+}
+
+fn parse_spec(spec: &str, length: u64) -> Result<Option<ByteRange>, ()> {
+    let (first, last) = spec.trim().split_once('-').ok_or(())?;
+
+    let (first, last) = (first.trim(), last.trim());
+
+    if first.is_empty() {
+        let suffix = last.parse::<u64>().map_err(|_| ())?;
+
+        if suffix == 0 || length == 0 {
+            return Ok(None);
+        }
+
+        return Ok(Some(ByteRange {
+            start: length.saturating_sub(suffix),
+            end: length - 1,
+        }));
+    }
+
+    let start = first.parse::<u64>().map_err(|_| ())?;
+
+    let end = match last.is_empty() {
+        true => u64::MAX,
+        false => last.parse::<u64>().map_err(|_| ())?,
+    };
+
+    if end < start {
+        return Err(());
+    }
+
+    if start >= length {
+        return Ok(None);
+    }
+
+    Ok(Some(ByteRange {
+        start,
+        end: end.min(length - 1),
+    }))
+}
+
+pub fn parse_range(header: &str, length: u64) -> RangeResult {
+    let specs = match header.trim().split_once('=') {
+        Some((unit, specs)) if unit.trim().eq_ignore_ascii_case("bytes") => specs,
+        _ => return RangeResult::Ignored,
+    };
+
+    let specs: Vec<&str> = specs.split(',').filter(|s| !s.trim().is_empty()).collect();
+
+    if specs.is_empty() || specs.len() > MAX_RANGES {
+        return RangeResult::Ignored;
+    }
+
+    let mut ranges = vec![];
+
+    for spec in specs {
+        match parse_spec(spec, length) {
+            Ok(Some(range)) => ranges.push(range),
+            Ok(None) => {}
+            Err(_) => return RangeResult::Ignored,
+        }
+    }
+
+    match ranges.is_empty() {
+        true => RangeResult::Unsatisfiable,
+        false => RangeResult::Satisfiable(ranges),
+    }
+}
+
+pub fn if_range_matches(
+    request: &Request,
+    etag: Option<&ETag>,
+    last_modified: Option<DateTime<Utc>>,
+) -> bool {
+    let header = match request.get_header("if-range") {
+        Some(header) => header,
+        None => return true,
+    };
+
+    if let Some(candidate) = ETag::parse(&header) {
+        return match etag {
+            Some(etag) => candidate.strong_eq(etag),
+            None => false,
+        };
+    }
+
+    match (parse_http_date(&header), last_modified) {
+        (Some(date), Some(modified)) => date.timestamp() == modified.timestamp(),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::{HttpStatus, MimeType};
+    use crate::conditional::format_http_date;
+    use crate::parser::test_request;
+    use crate::response::Response;
+
+    const BODY: &str = "0123456789";
+
+    fn request(headers: &[&str]) -> Request {
+        test_request("GET", "/doc", &[&["Host: localhost"], headers].concat(), "")
+    }
+
+    fn ranges(header: &str) -> Option<Vec<(u64, u64)>> {
+        match parse_range(header, BODY.len() as u64) {
+            RangeResult::Satisfiable(ranges) => {
+                Some(ranges.into_iter().map(|r| (r.start, r.end)).collect())
+            }
+            _ => None,
+        }
+    }
+
+    fn ranged(headers: &[&str]) -> (Response, String) {
+        let mut resp =
+            Response::new_string(BODY.to_string(), MimeType::TextPlain, HttpStatus::Http200Ok);
+
+        resp.enable_ranges();
+        resp.set_etag(ETag::strong("v1"));
+        resp.apply_range(&request(headers));
+
+        let raw = String::from_utf8(resp.compose().into_bytes()).unwrap();
+        let body = raw.split_once("\r\n\r\n").unwrap().1.to_string();
+
+        (resp, body)
+    }
+
+    #[test]
+    fn parses_closed_open_and_suffix_ranges() {
+        assert_eq!(ranges("bytes=0-3"), Some(vec![(0, 3)]));
+        assert_eq!(ranges("bytes=7-"), Some(vec![(7, 9)]));
+        assert_eq!(ranges("bytes=-4"), Some(vec![(6, 9)]));
+        assert_eq!(ranges("bytes=-40"), Some(vec![(0, 9)]));
+        assert_eq!(ranges("bytes=5-100"), Some(vec![(5, 9)]));
+        assert_eq!(ranges("bytes= 0-1 , 20-30, 8-"), Some(vec![(0, 1), (8, 9)]));
+    }
+
+    #[test]
+    fn classifies_unsatisfiable_and_ignored_ranges() {
+        let length = BODY.len() as u64;
+
+        for header in ["bytes=10-", "bytes=20-30", "bytes=-0"] {
+            assert!(matches!(
+                parse_range(header, length),
+                RangeResult::Unsatisfiable
+            ));
+        }
+
+        for header in ["items=0-1", "bytes=3-1", "bytes=a-b", "bytes=", "bytes=1"] {
+            assert!(matches!(parse_range(header, length), RangeResult::Ignored));
+        }
+
+        let too_many = format!("bytes={}", vec!["0-0"; MAX_RANGES + 1].join(","));
+        assert!(matches!(
+            parse_range(&too_many, length),
+            RangeResult::Ignored
+        ));
+    }
+
+    #[test]
+    fn serves_a_single_range() {
+        let (resp, body) = ranged(&["Range: bytes=-3"]);
+
+        assert!(matches!(
+            resp.get_status(),
+            HttpStatus::Http206PartialContent
+        ));
+        assert_eq!(
+            resp.get_header("Content-Range").as_deref(),
+            Some("bytes 7-9/10")
+        );
+        assert_eq!(body, "789");
+    }
+
+    #[test]
+    fn serves_multipart_byteranges() {
+        let (resp, body) = ranged(&["Range: bytes=0-1,5-"]);
+
+        assert!(matches!(
+            resp.get_status(),
+            HttpStatus::Http206PartialContent
+        ));
+
+        let ctype = resp.get_header("Content-Type").unwrap();
+        let boundary = ctype
+            .strip_prefix("multipart/byteranges; boundary=")
+            .unwrap();
+
+        let expected = format!(
+            concat!(
+                "\r\n--{b}\r\nContent-Type: text/plain\r\nContent-Range: bytes 0-1/10\r\n\r\n01",
+                "\r\n--{b}\r\nContent-Type: text/plain\r\nContent-Range: bytes 5-9/10\r\n\r\n56789",
+                "\r\n--{b}--\r\n"
+            ),
+            b = boundary
+        );
+
+        assert_eq!(body, expected);
+    }
+
+    #[test]
+    fn answers_416_for_unsatisfiable_ranges() {
+        let (resp, _) = ranged(&["Range: bytes=50-"]);
+
+        assert!(matches!(
+            resp.get_status(),
+            HttpStatus::Http416RangeNotSatisfiable
+        ));
+        assert_eq!(
+            resp.get_header("Content-Range").as_deref(),
+            Some("bytes */10")
+        );
+    }
+
+    #[test]
+    fn if_range_must_match_the_current_validators() {
+        let (resp, body) = ranged(&["Range: bytes=0-1", "If-Range: \"v1\""]);
+        assert!(matches!(
+            resp.get_status(),
+            HttpStatus::Http206PartialContent
+        ));
+        assert_eq!(body, "01");
+
+        for if_range in ["If-Range: \"v2\"", "If-Range: W/\"v1\""] {
+            let (resp, body) = ranged(&["Range: bytes=0-1", if_range]);
+
+            assert!(matches!(resp.get_status(), HttpStatus::Http200Ok));
+            assert_eq!(body, BODY);
+        }
+
+        let modified = parse_http_date("Sun, 18 Oct 2026 10:00:00 GMT").unwrap();
+        let header = format!("If-Range: {}", format_http_date(&modified));
+        let req = request(&[header.as_str()]);
+
+        assert!(if_range_matches(&req, None, Some(modified)));
+        assert!(!if_range_matches(&req, None, None));
+        assert!(if_range_matches(&request(&[]), None, None));
+    }
+}
@@ -1,12 +1,26 @@
 use std::net::{SocketAddr, TcpStream};
-
-use crate::{common::*, parser::RequestParser};
+use std::time::Duration;
+
+use crate::{
+    common::*,
+    extensions::Extensions,
+    negotiation,
+    parser::{ParseError, RequestParser, DEFAULT_MAX_BODY_SIZE, DEFAULT_READ_TIMEOUT},
+    router::{self, PathNormalization, PathParamError, UrlTable},
+    state::StateMap,
+};
+use std::str::FromStr;
+use std::sync::Arc;
 
 pub struct Request {
     headers: Vec<Header>,
     body: RequestBody,
     uri: String,
     uri_params: Vec<Params>,
+    path_params: Vec<Params>,
+    url_table: Arc<UrlTable>,
+    state: Arc<StateMap>,
+    extensions: Extensions,
     uri_paths: Vec<String>,
     bare_uri: String,
     method: Method,
@@ -21,10 +35,20 @@ pub struct Request {
 }
 
 impl Request {
-    pub fn from(s: &TcpStream) -> Self {
-        let req = RequestParser::parse_and_create(s);
+    pub fn from(s: &TcpStream) -> Result<Self, ParseError> {
+        RequestParser::parse_and_create(s, DEFAULT_MAX_BODY_SIZE, DEFAULT_READ_TIMEOUT)
+    }
 
-        req
+    pub fn from_limited(s: &TcpStream, max_body_size: usize) -> Result<Self, ParseError> {
+        RequestParser::parse_and_create(s, max_body_size, DEFAULT_READ_TIMEOUT)
+    }
+
+    pub fn from_limited_timeout(
+        s: &TcpStream,
+        max_body_size: usize,
+        timeout: Duration,
+    ) -> Result<Self, ParseError> {
+        RequestParser::parse_and_create(s, max_body_size, timeout)
     }
 
     pub fn new(
@@ -53,6 +77,10 @@ impl Request {
             content_type,
             scheme,
             uri_params,
+            path_params: vec![],
+            url_table: Arc::new(UrlTable::default()),
+            state: Arc::new(StateMap::default()),
+            extensions: Extensions::new(),
             userinfo,
             bare_uri,
             port,
@@ -70,23 +98,14 @@ impl Request {
     }
 
     pub fn compose_name_for_ep(&self) -> String {
-        let ep_name = self.uri.clone();
-
-        let mut ep_name_chars = ep_name.chars().collect::<Vec<char>>();
-
-        if ep_name_chars.len() > 2 {
-            if &ep_name_chars[0] == &'/' {
-                ep_name_chars.remove(0);
-            }
-
-            if &ep_name_chars.last().unwrap() == &&'/' {
-                ep_name_chars.pop();
-            }
+        match PathNormalization::default().normalize(&self.uri) {
+            Some(normalized) => normalized.path,
+            None => self.uri.clone(),
         }
+    }
 
-        let ep_name = String::from_iter(ep_name_chars.iter());
-
-        ep_name
+    pub fn set_uri(&mut self, uri: String) {
+        self.uri = uri;
     }
 
     pub fn get_raw_uri(&self) -> String {
@@ -149,6 +168,54 @@ impl Request {
         self.uri_params.clone()
     }
 
+    pub fn set_path_params(&mut self, path_params: Vec<Params>) {
+        self.path_params = path_params;
+    }
+
+    pub fn get_path_params(&self) -> Vec<Params> {
+        self.path_params.clone()
+    }
+
+    pub fn get_path_param(&self, key: &str) -> Option<String> {
+        self.path_params
+            .iter()
+            .find(|p| p.key == key)
+            .map(|p| p.value.clone())
+    }
+
+    pub fn path_param<T: FromStr>(&self, key: &str) -> Result<T, PathParamError> {
+        router::parse_param(&self.path_params, key)
+    }
+
+    pub fn set_url_table(&mut self, url_table: Arc<UrlTable>) {
+        self.url_table = url_table;
+    }
+
+    pub fn url_for(
+        &self,
+        name: &str,
+        params: &[(&str, &str)],
+        query: &[(&str, &str)],
+    ) -> Result<String, String> {
+        self.url_table.url_for(name, params, query)
+    }
+
+    pub fn set_state(&mut self, state: Arc<StateMap>) {
+        self.state = state;
+    }
+
+    pub fn get_state<T: Send + Sync + 'static>(&self) -> Option<Arc<T>> {
+        self.state.get::<T>()
+    }
+
+    pub fn extensions(&self) -> &Extensions {
+        &self.extensions
+    }
+
+    pub fn extensions_mut(&mut self) -> &mut Extensions {
+        &mut self.extensions
+    }
+
     pub fn get_paths(&self) -> Vec<String> {
         self.uri_paths.clone()
     }
@@ -160,4 +227,17 @@ impl Request {
     pub fn get_all_headers(&self) -> Vec<Header> {
         self.headers.clone()
     }
+
+    pub fn accepts(&self, mime: &MimeType) -> bool {
+        match self.get_header("accept") {
+            Some(header) => {
+                negotiation::quality_of(&negotiation::parse_accept(&header), mime) > 0.0
+            }
+            None => true,
+        }
+    }
+
+    pub fn preferred_type(&self, offered: &[MimeType]) -> Option<MimeType> {
+        negotiation::negotiate(self.get_header("accept"), offered)
+    }
 }
@@ -1,25 +1,33 @@
 use crate::common::*;
+use crate::compression::{self, CompressionConfig, Encoding};
+use crate::conditional::{self, format_http_date, parse_http_date, ETag, ETagKind, Validators};
+use crate::extensions::Extensions;
+use crate::range::{self, ByteRange, RangeResult};
+use crate::request::Request;
 use chrono::{DateTime, Utc};
-use serde::Deserialize;
+use serde::Serialize;
+use std::fs::{metadata, File};
+use std::io::{Read, Seek};
+use std::path::Path;
 
-pub struct Response<'a, T: Clone + ResponseCommon + Deserialize<'a>> {
+pub struct Response {
     headers: Vec<Header>,
     server: String,
     status: HttpStatus,
     content_type: MimeType,
-    body: ResponseBody<'a, T>,
-    content_length: usize,
+    body: ResponseBody,
     datetime: DateTime<Utc>,
+    extensions: Extensions,
+    omit_body: bool,
 }
 
-impl<'a, T: Clone + ResponseCommon + Deserialize<'a>> Response<'a, T> {
+impl Response {
     pub fn new(
         headers: Vec<Header>,
         server: String,
         status: HttpStatus,
         content_type: MimeType,
-        body: ResponseBody<'a, T>,
-        content_length: usize,
+        body: ResponseBody,
     ) -> Self {
         Response {
             headers,
@@ -27,14 +35,15 @@ impl<'a, T: Clone + ResponseCommon + Deserialize<'a>> Response<'a, T> {
             status,
             content_type,
             body,
-            content_length,
             datetime: Utc::now(),
+            extensions: Extensions::new(),
+            omit_body: false,
         }
     }
-    pub fn new_json(object: T, status: HttpStatus) -> Self {
-        let body = ResponseBody::new_json(object.clone());
+
+    pub fn new_json<T: Serialize>(object: T, status: HttpStatus) -> Self {
+        let body = ResponseBody::new_json(object);
         let server = String::from("Samovar/0.0.1b");
-        let content_length = object.get_length();
         let content_type = MimeType::ApplicationJson;
         let datetime = Utc::now();
         let headers = vec![];
@@ -45,15 +54,33 @@ impl<'a, T: Clone + ResponseCommon + Deserialize<'a>> Response<'a, T> {
             status,
             content_type,
             body,
-            content_length,
             datetime,
+            extensions: Extensions::new(),
+            omit_body: false,
         }
     }
 
     pub fn new_string(t: String, content_type: MimeType, status: HttpStatus) -> Self {
-        let body = ResponseBody::new_string(content_type.clone(), t.clone());
+        let body = ResponseBody::new_string(content_type.clone(), t);
+        let server = String::from("Samovar/0.0.1b");
+        let datetime = Utc::now();
+        let headers = vec![];
+
+        Response {
+            headers,
+            server,
+            status,
+            content_type,
+            body,
+            datetime,
+            extensions: Extensions::new(),
+            omit_body: false,
+        }
+    }
+
+    pub fn new_bytes(b: Vec<u8>, content_type: MimeType, status: HttpStatus) -> Self {
+        let body = ResponseBody::new_bytes(content_type.clone(), b);
         let server = String::from("Samovar/0.0.1b");
-        let content_length = t.len();
         let datetime = Utc::now();
         let headers = vec![];
 
@@ -63,13 +90,90 @@ impl<'a, T: Clone + ResponseCommon + Deserialize<'a>> Response<'a, T> {
             status,
             content_type,
             body,
-            content_length,
             datetime,
+            extensions: Extensions::new(),
+            omit_body: false,
         }
     }
 
+    pub fn new_seekable<R: Read + Seek + Send + 'static>(
+        reader: R,
+        content_type: MimeType,
+    ) -> std::io::Result<Self> {
+        let body = ResponseBody::new_seekable(content_type.clone(), Box::new(reader))?;
+        let server = String::from("Samovar/0.0.1b");
+        let datetime = Utc::now();
+        let headers = vec![];
+
+        let mut resp = Response {
+            headers,
+            server,
+            status: HttpStatus::Http200Ok,
+            content_type,
+            body,
+            datetime,
+            extensions: Extensions::new(),
+            omit_body: false,
+        };
+
+        resp.enable_ranges();
+
+        Ok(resp)
+    }
+
+    pub fn new_file<P: AsRef<Path>>(path: P, content_type: MimeType) -> std::io::Result<Self> {
+        let modified = metadata(&path)?.modified()?;
+        let file = File::open(&path)?;
+
+        let mut resp = Self::new_seekable(file, content_type)?;
+        resp.set_last_modified(DateTime::<Utc>::from(modified));
+
+        Ok(resp)
+    }
+
+    pub fn extensions(&self) -> &Extensions {
+        &self.extensions
+    }
+
+    pub fn extensions_mut(&mut self) -> &mut Extensions {
+        &mut self.extensions
+    }
+
+    pub fn precondition(status: HttpStatus, current: Option<&Validators>) -> Self {
+        let mut resp = match status {
+            HttpStatus::Http304NotModified => {
+                Response::new_bytes(vec![], MimeType::TextPlain, status)
+            }
+            _ => status.into_response(),
+        };
+
+        if let Some(current) = current {
+            if let Some(etag) = current.get_etag() {
+                resp.set_etag(etag);
+            }
+
+            if let Some(datetime) = current.get_last_modified() {
+                resp.set_last_modified(datetime);
+            }
+        }
+
+        resp
+    }
+
+    pub fn omit_body(&mut self) {
+        self.omit_body = true;
+    }
+
+    pub fn set_status(&mut self, status: HttpStatus) {
+        self.status = status;
+    }
+
+    pub fn get_status(&self) -> HttpStatus {
+        self.status
+    }
+
     fn format_date_add_header(&mut self) {
-        let str_date = self.datetime.format("%a, %d %B %Y %T %Z").to_string();
+        let str_date = format_http_date(&self.datetime);
         let header_date = Header {
             key: "Date".to_string(),
             value: str_date,
@@ -79,13 +183,17 @@ impl<'a, T: Clone + ResponseCommon + Deserialize<'a>> Response<'a, T> {
     }
 
     fn format_content_add_header(&mut self) {
+        if matches!(self.status, HttpStatus::Http304NotModified) {
+            return;
+        }
+
         let header_ctype = Header {
             key: "Content-Type".to_string(),
             value: self.content_type.clone().into(),
         };
         let header_clength = Header {
             key: "Content-Length".to_string(),
-            value: self.content_length.to_string(),
+            value: self.body.len().to_string(),
         };
 
         self.headers.push(header_ctype);
@@ -121,14 +229,241 @@ impl<'a, T: Clone + ResponseCommon + Deserialize<'a>> Response<'a, T> {
         }
     }
 
-    fn make_body(&self) -> String {
-        match self.body.content.clone() {
-            ResponseBodyType::Object(obj) => obj.parse_to_string(),
-            ResponseBodyType::Str(t) => t,
-            ResponseBodyType::PhantomData(_) => "".to_string(),
+    pub fn get_header(&self, key: &str) -> Option<String> {
+        self.headers
+            .iter()
+            .find(|h| h.key.eq_ignore_ascii_case(key))
+            .map(|h| h.value.clone())
+    }
+
+    pub fn add_vary(&mut self, value: &str) {
+        match self.get_header("Vary") {
+            Some(vary) => {
+                if !vary
+                    .split(',')
+                    .any(|v| v.trim().eq_ignore_ascii_case(value))
+                {
+                    self.set_header("Vary".to_string(), format!("{}, {}", vary, value));
+                }
+            }
+            None => self.set_header("Vary".to_string(), value.to_string()),
+        }
+    }
+
+    pub fn compress(&mut self, request: &Request, config: &CompressionConfig) {
+        if self.get_header("Content-Encoding").is_some()
+            || config.is_skipped(&self.content_type)
+            || self.body.is_seekable()
+        {
+            return;
+        }
+
+        if matches!(self.status, HttpStatus::Http304NotModified) {
+            self.add_vary("Accept-Encoding");
+
+            return;
+        }
+
+        if matches!(
+            self.status,
+            HttpStatus::Http204NoContent | HttpStatus::Http206PartialContent
+        ) {
+            return;
+        }
+
+        if self.body.len() < config.get_min_size() {
+            return;
+        }
+
+        self.add_vary("Accept-Encoding");
+
+        let encoding = config.negotiate(request.get_header("accept-encoding"));
+
+        if encoding == Encoding::Identity {
+            return;
+        }
+
+        let bytes = match self.body.to_bytes() {
+            Ok(bytes) => bytes,
+            Err(e) => return self.fail(e),
+        };
+
+        let compressed = compression::compress(&bytes, encoding, config.get_level());
+
+        self.body = ResponseBody::new_bytes(self.content_type.clone(), compressed);
+        self.set_header("Content-Encoding".to_string(), encoding.into());
+
+        if let Some(etag) = self.get_etag() {
+            self.set_etag(etag.to_weak());
+        }
+    }
+
+    pub fn set_etag(&mut self, etag: ETag) {
+        self.set_header("ETag".to_string(), etag.into());
+    }
+
+    pub fn get_etag(&self) -> Option<ETag> {
+        self.get_header("ETag").and_then(|e| ETag::parse(&e))
+    }
+
+    pub fn set_last_modified(&mut self, datetime: DateTime<Utc>) {
+        self.set_header("Last-Modified".to_string(), format_http_date(&datetime));
+    }
+
+    pub fn get_last_modified(&self) -> Option<DateTime<Utc>> {
+        self.get_header("Last-Modified")
+            .and_then(|d| parse_http_date(&d))
+    }
+
+    pub fn apply_conditional(&mut self, request: &Request, auto_etag: Option<ETagKind>) {
+        if !self.status.is_success() {
+            return;
+        }
+
+        if let (None, Some(kind), false) = (self.get_etag(), auto_etag, self.body.is_seekable()) {
+            match self.body.to_bytes() {
+                Ok(bytes) => self.set_etag(ETag::from_bytes(&bytes, kind)),
+                Err(e) => return self.fail(e),
+            }
+        }
+
+        if !request.get_method().is_safe() {
+            return;
+        }
+
+        let etag = self.get_etag();
+
+        match conditional::evaluate(request, etag.as_ref(), self.get_last_modified()) {
+            Some(HttpStatus::Http304NotModified) => {
+                self.status = HttpStatus::Http304NotModified;
+                self.body = ResponseBody::new_bytes(self.content_type.clone(), vec![]);
+            }
+            Some(status) => {
+                let status_string: String = status.into();
+
+                self.status = status;
+                self.content_type = MimeType::TextPlain;
+                self.body = ResponseBody::new_string(MimeType::TextPlain, status_string);
+            }
+            None => {}
+        }
+    }
+
+    pub fn enable_ranges(&mut self) {
+        self.set_header("Accept-Ranges".to_string(), "bytes".to_string());
+    }
+
+    pub fn accepts_ranges(&self) -> bool {
+        matches!(self.get_header("Accept-Ranges"), Some(v) if v.eq_ignore_ascii_case("bytes"))
+    }
+
+    fn multipart_boundary(&self) -> String {
+        format!(
+            "samovar-{:016x}",
+            self.datetime.timestamp_nanos_opt().unwrap_or_default()
+        )
+    }
+
+    fn make_multipart(
+        &self,
+        ranges: &[ByteRange],
+        total: u64,
+        boundary: &str,
+    ) -> std::io::Result<Vec<u8>> {
+        let ctype: String = self.content_type.clone().into();
+
+        let mut ret = vec![];
+
+        for r in ranges {
+            let part_head = format!(
+                "\r\n--{}\r\nContent-Type: {}\r\nContent-Range: {}\r\n\r\n",
+                boundary,
+                ctype,
+                r.content_range(total)
+            );
+
+            ret.extend(part_head.into_bytes());
+            ret.extend(self.body.read_range(r.start, r.end + 1)?);
+        }
+
+        ret.extend(format!("\r\n--{}--\r\n", boundary).into_bytes());
+
+        Ok(ret)
+    }
+
+    pub fn apply_range(&mut self, request: &Request) {
+        if !matches!(self.status, HttpStatus::Http200Ok)
+            || request.get_method() != Method::GET
+            || !self.accepts_ranges()
+            || self.get_header("Content-Encoding").is_some()
+        {
+            return;
+        }
+
+        let header = match request.get_header("range") {
+            Some(header) => header,
+            None => return,
+        };
+
+        if !range::if_range_matches(request, self.get_etag().as_ref(), self.get_last_modified()) {
+            return;
+        }
+
+        let total = self.body.len() as u64;
+
+        match range::parse_range(&header, total) {
+            RangeResult::Ignored => {}
+            RangeResult::Unsatisfiable => {
+                let status_string: String = HttpStatus::Http416RangeNotSatisfiable.into();
+
+                self.status = HttpStatus::Http416RangeNotSatisfiable;
+                self.content_type = MimeType::TextPlain;
+                self.body = ResponseBody::new_string(MimeType::TextPlain, status_string);
+                self.set_header("Content-Range".to_string(), format!("bytes */{}", total));
+            }
+            RangeResult::Satisfiable(ranges) if ranges.len() == 1 => {
+                let r = ranges[0];
+
+                let part = match self.body.read_range(r.start, r.end + 1) {
+                    Ok(part) => part,
+                    Err(e) => return self.fail(e),
+                };
+
+                self.status = HttpStatus::Http206PartialContent;
+                self.body = ResponseBody::new_bytes(self.content_type.clone(), part);
+                self.set_header("Content-Range".to_string(), r.content_range(total));
+            }
+            RangeResult::Satisfiable(ranges) => {
+                let boundary = self.multipart_boundary();
+                let multipart = match self.make_multipart(&ranges, total, &boundary) {
+                    Ok(multipart) => multipart,
+                    Err(e) => return self.fail(e),
+                };
+                let ctype = MimeType::Other(format!("multipart/byteranges; boundary={}", boundary));
+
+                self.status = HttpStatus::Http206PartialContent;
+                self.content_type = ctype.clone();
+                self.body = ResponseBody::new_bytes(ctype, multipart);
+            }
+        }
+    }
+
+    fn make_body(&self) -> std::io::Result<Vec<u8>> {
+        match self.omit_body {
+            true => Ok(vec![]),
+            false => self.body.to_bytes(),
         }
     }
 
+    fn fail(&mut self, e: std::io::Error) {
+        println!("Could not read response body: {}", e);
+
+        let omit_body = self.omit_body;
+
+        *self = HttpStatus::Http500InternalServerError.into_response();
+        self.omit_body = omit_body;
+    }
+
     fn make_header_single(h: &Header) -> String {
         let ret = format!("{}: {}", h.key, h.value);
 
@@ -145,25 +480,379 @@ impl<'a, T: Clone + ResponseCommon + Deserialize<'a>> Response<'a, T> {
         let h_joined = self
             .headers
             .iter()
-            .map(|x| Self::make_header_single(x))
+            .map(Self::make_header_single)
             .collect::<Vec<String>>()
             .join(&cchar_vec);
         h_joined
     }
 
     pub fn compose(&mut self) -> ResponseTextWrapper {
+        let body = match self.make_body() {
+            Ok(body) => body,
+            Err(e) => {
+                self.fail(e);
+                self.make_body().unwrap_or_default()
+            }
+        };
+
         let metadata = self.format_response_metadata();
         let headers_joined = self.make_header();
-        let body = self.make_body();
 
         let cchar_vec = String::from_utf8(vec![13u8, 10u8]).unwrap();
         let cchar_vec_double = String::from_utf8(vec![13u8, 10u8, 13u8, 10u8]).unwrap();
 
-        let ret = format!(
-            "{}{}{}{}{}",
-            metadata, cchar_vec, headers_joined, cchar_vec_double, body
+        let mut ret = format!(
+            "{}{}{}{}",
+            metadata, cchar_vec, headers_joined, cchar_vec_double
+        )
+        .into_bytes();
+
+        ret.extend(body);
+
+        ResponseTextWrapper::new_bytes(ret)
+    }
+}
+
+pub trait IntoResponse {
+    fn into_response(self) -> Response;
+}
+
+impl IntoResponse for Response {
+    fn into_response(self) -> Response {
+        self
+    }
+}
+
+impl IntoResponse for String {
+    fn into_response(self) -> Response {
+        Response::new_string(self, MimeType::TextPlain, HttpStatus::Http200Ok)
+    }
+}
+
+impl IntoResponse for &str {
+    fn into_response(self) -> Response {
+        self.to_string().into_response()
+    }
+}
+
+impl IntoResponse for Vec<u8> {
+    fn into_response(self) -> Response {
+        Response::new_bytes(
+            self,
+            MimeType::ApplicationOctetStream,
+            HttpStatus::Http200Ok,
+        )
+    }
+}
+
+impl IntoResponse for HttpStatus {
+    fn into_response(self) -> Response {
+        let status_string: String = self.into();
+
+        Response::new_string(status_string, MimeType::TextPlain, self)
+    }
+}
+
+impl<T: IntoResponse> IntoResponse for (HttpStatus, T) {
+    fn into_response(self) -> Response {
+        let (status, t) = self;
+
+        let mut resp = t.into_response();
+        resp.set_status(status);
+
+        resp
+    }
+}
+
+impl<T: IntoResponse, E: IntoResponse> IntoResponse for Result<T, E> {
+    fn into_response(self) -> Response {
+        match self {
+            Ok(t) => t.into_response(),
+            Err(e) => e.into_response(),
+        }
+    }
+}
+
+pub struct Json<T>(pub T);
+
+impl<T: Serialize> IntoResponse for Json<T> {
+    fn into_response(self) -> Response {
+        let Json(object) = self;
+
+        Response::new_json(object, HttpStatus::Http200Ok)
+    }
+}
+
+pub struct Redirect {
+    location: String,
+    status: HttpStatus,
+}
+
+impl Redirect {
+    pub fn to(location: &str) -> Self {
+        Redirect {
+            location: location.to_string(),
+            status: HttpStatus::Http303SeeOther,
+        }
+    }
+
+    pub fn temporary(location: &str) -> Self {
+        Redirect {
+            location: location.to_string(),
+            status: HttpStatus::Http307TemporaryRedirect,
+        }
+    }
+
+    pub fn permanent(location: &str) -> Self {
+        Redirect {
+            location: location.to_string(),
+            status: HttpStatus::Http308PermanentRedirect,
+        }
+    }
+}
+
+impl IntoResponse for Redirect {
+    fn into_response(self) -> Response {
+        let mut resp = Response::new_string(String::new(), MimeType::TextPlain, self.status);
+        resp.set_header("Location".to_string(), self.location);
+
+        resp
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn composed(mut resp: Response) -> (String, Vec<u8>) {
+        let bytes = resp.compose().into_bytes();
+        let split = bytes.windows(4).position(|w| w == b"\r\n\r\n").unwrap();
+
+        (
+            String::from_utf8(bytes[..split].to_vec()).unwrap(),
+            bytes[split + 4..].to_vec(),
+        )
+    }
+
+    fn header(head: &str, name: &str) -> Option<String> {
+        head.lines()
+            .filter_map(|line| line.split_once(": "))
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.trim().to_string())
+    }
+
+    #[test]
+    fn sends_binary_bodies_untouched() {
+        let payload = vec![0u8, 159, 146, 150, 255, 13, 10, 0];
+
+        let (head, body) = composed(Response::new_bytes(
+            payload.clone(),
+            MimeType::ApplicationOctetStream,
+            HttpStatus::Http200Ok,
+        ));
+
+        assert_eq!(body, payload);
+        assert_eq!(header(&head, "Content-Length").as_deref(), Some("8"));
+        assert_eq!(
+            header(&head, "Content-Type").as_deref(),
+            Some("application/octet-stream")
+        );
+    }
+
+    #[test]
+    fn counts_bytes_not_characters() {
+        let (head, body) = composed(Response::new_string(
+            "čaj ☕".to_string(),
+            MimeType::TextPlain,
+            HttpStatus::Http200Ok,
+        ));
+
+        assert_eq!(body, "čaj ☕".as_bytes());
+        assert_eq!(header(&head, "Content-Length").as_deref(), Some("8"));
+    }
+
+    #[derive(Serialize)]
+    struct Order {
+        id: u32,
+        #[serde(rename = "itemName")]
+        item: String,
+        tags: Vec<&'static str>,
+        note: Option<String>,
+    }
+
+    fn order() -> Order {
+        Order {
+            id: 7,
+            item: "čaj".to_string(),
+            tags: vec!["hot"],
+            note: None,
+        }
+    }
+
+    #[test]
+    fn serializes_json_bodies_with_serde() {
+        let (head, body) = composed(Response::new_json(order(), HttpStatus::Http201Created));
+
+        let expected = r#"{"id":7,"itemName":"čaj","tags":["hot"],"note":null}"#;
+
+        assert!(head.starts_with("HTTP/1.1 201"));
+        assert_eq!(String::from_utf8(body).unwrap(), expected);
+        assert_eq!(
+            header(&head, "Content-Length"),
+            Some(expected.len().to_string())
+        );
+        assert_eq!(
+            header(&head, "Content-Type").as_deref(),
+            Some("application/json")
+        );
+    }
+
+    #[test]
+    fn serializes_any_serialize_type() {
+        let (_, body) = composed(Response::new_json(vec![(1, "a")], HttpStatus::Http200Ok));
+
+        assert_eq!(body, br#"[[1,"a"]]"#);
+    }
+
+    fn status_line(head: &str) -> &str {
+        head.lines().next().unwrap()
+    }
+
+    #[test]
+    fn strings_become_plain_text() {
+        let (head, body) = composed("tea".into_response());
+
+        assert_eq!(status_line(&head), "HTTP/1.1 200 OK");
+        assert_eq!(header(&head, "Content-Type").as_deref(), Some("text/plain"));
+        assert_eq!(body, b"tea");
+
+        let (_, body) = composed(String::from("pot").into_response());
+
+        assert_eq!(body, b"pot");
+    }
+
+    #[test]
+    fn byte_vectors_become_octet_streams() {
+        let (head, body) = composed(vec![1u8, 2, 3].into_response());
+
+        assert_eq!(
+            header(&head, "Content-Type").as_deref(),
+            Some("application/octet-stream")
+        );
+        assert_eq!(body, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn status_tuples_override_the_status() {
+        let (head, body) = composed((HttpStatus::Http201Created, "made").into_response());
+
+        assert!(status_line(&head).starts_with("HTTP/1.1 201"));
+        assert_eq!(body, b"made");
+
+        let (head, _) = composed(HttpStatus::Http404NotFound.into_response());
+
+        assert!(status_line(&head).starts_with("HTTP/1.1 404"));
+    }
+
+    #[test]
+    fn results_respond_with_either_side() {
+        let ok: Result<&str, (HttpStatus, &str)> = Ok("fine");
+        let err: Result<&str, (HttpStatus, &str)> = Err((HttpStatus::Http400BadRequest, "bad"));
+
+        let (head, body) = composed(ok.into_response());
+
+        assert!(status_line(&head).starts_with("HTTP/1.1 200"));
+        assert_eq!(body, b"fine");
+
+        let (head, body) = composed(err.into_response());
+
+        assert!(status_line(&head).starts_with("HTTP/1.1 400"));
+        assert_eq!(body, b"bad");
+    }
+
+    #[test]
+    fn json_wrappers_serialize_their_value() {
+        let (head, body) = composed(Json(order()).into_response());
+
+        assert!(status_line(&head).starts_with("HTTP/1.1 200"));
+        assert_eq!(
+            header(&head, "Content-Type").as_deref(),
+            Some("application/json")
+        );
+        assert!(body.starts_with(br#"{"id":7"#));
+    }
+
+    #[test]
+    fn redirects_set_status_and_location() {
+        let cases = [
+            (Redirect::to("/a"), "303"),
+            (Redirect::temporary("/b"), "307"),
+            (Redirect::permanent("/c"), "308"),
+        ];
+
+        for (redirect, code) in cases {
+            let location = redirect.location.clone();
+            let (head, body) = composed(redirect.into_response());
+
+            assert!(status_line(&head).starts_with(&format!("HTTP/1.1 {}", code)));
+            assert_eq!(header(&head, "Location"), Some(location));
+            assert!(body.is_empty());
+        }
+    }
+
+    struct Broken;
+
+    impl Read for Broken {
+        fn read(&mut self, _buf: &mut [u8]) -> std::io::Result<usize> {
+            Err(std::io::Error::other("disk on fire"))
+        }
+    }
+
+    impl Seek for Broken {
+        fn seek(&mut self, _pos: std::io::SeekFrom) -> std::io::Result<u64> {
+            Ok(1024)
+        }
+    }
+
+    fn seekable(data: &[u8]) -> Response {
+        Response::new_seekable(std::io::Cursor::new(data.to_vec()), MimeType::TextPlain).unwrap()
+    }
+
+    #[test]
+    fn unreadable_bodies_become_server_errors() {
+        let (head, body) = composed(Response::new_seekable(Broken, MimeType::TextPlain).unwrap());
+
+        assert!(status_line(&head).starts_with("HTTP/1.1 500"));
+        assert_eq!(
+            header(&head, "Content-Length"),
+            Some(body.len().to_string())
         );
 
-        ResponseTextWrapper::new(ret)
+        let mut resp = Response::new_seekable(Broken, MimeType::TextPlain).unwrap();
+        resp.apply_range(&crate::parser::test_request(
+            "GET",
+            "/",
+            &["Range: bytes=0-9"],
+            "",
+        ));
+
+        assert!(matches!(
+            resp.get_status(),
+            HttpStatus::Http500InternalServerError
+        ));
+    }
+
+    #[test]
+    fn seekable_bodies_are_not_compressed() {
+        let request = crate::parser::test_request("GET", "/", &["Accept-Encoding: gzip"], "");
+        let data = "a".repeat(4096);
+
+        let mut resp = seekable(data.as_bytes());
+        resp.compress(&request, &CompressionConfig::new());
+
+        let (head, body) = composed(resp);
+
+        assert_eq!(header(&head, "Content-Encoding"), None);
+        assert_eq!(body, data.as_bytes());
     }
 }
@@ -0,0 +1,1802 @@
+use crate::common::*;
+use crate::endpoint::Endpoint;
+use crate::guard::{self, Guard, GuardOutcome};
+use crate::middleware::{Middleware, MiddlewareRef};
+use crate::request::Request;
+use crate::response::{IntoResponse, Response};
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Arc;
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Segment {
+    Static(String),
+    Param(String),
+    Wildcard(String),
+}
+
+#[derive(Clone, Debug)]
+pub struct RoutePattern {
+    raw: String,
+    segments: Vec<Segment>,
+    trailing_slash: bool,
+}
+
+impl RoutePattern {
+    pub fn parse(path: &str) -> Self {
+        let segments = split_path(path)
+            .into_iter()
+            .map(|s| match s.chars().next() {
+                Some(':') => Segment::Param(s[1..].to_string()),
+                Some('*') => Segment::Wildcard(s[1..].to_string()),
+                _ => Segment::Static(s.to_string()),
+            })
+            .collect();
+
+        RoutePattern {
+            raw: path.to_string(),
+            trailing_slash: path.len() > 1 && path.ends_with('/'),
+            segments,
+        }
+    }
+
+    pub fn is_static(&self) -> bool {
+        self.segments
+            .iter()
+            .all(|s| matches!(s, Segment::Static(_)))
+    }
+
+    pub fn get_raw(&self) -> String {
+        self.raw.clone()
+    }
+
+    pub fn get_segments(&self) -> Vec<Segment> {
+        self.segments.clone()
+    }
+
+    pub fn build(&self, params: &[(&str, &str)]) -> Result<String, String> {
+        let find = |name: &String| {
+            params
+                .iter()
+                .find(|(k, _)| k == name)
+                .map(|(_, v)| *v)
+                .ok_or_else(|| format!("Missing parameter {} for {}", name, self.raw))
+        };
+
+        let mut segments: Vec<String> = vec![];
+
+        for segment in self.segments.iter() {
+            match segment {
+                Segment::Static(s) => segments.push(s.clone()),
+                Segment::Param(name) => segments.push(percent_encode(find(name)?)),
+                Segment::Wildcard(name) => segments.push(
+                    split_path(find(name)?)
+                        .into_iter()
+                        .map(percent_encode)
+                        .collect::<Vec<String>>()
+                        .join("/"),
+                ),
+            }
+        }
+
+        match self.trailing_slash && !segments.is_empty() {
+            true => Ok(format!("/{}/", segments.join("/"))),
+            false => Ok(format!("/{}", segments.join("/"))),
+        }
+    }
+
+    pub fn has_trailing_slash(&self) -> bool {
+        self.trailing_slash
+    }
+
+    pub fn matches(&self, uri: &str) -> Option<Vec<Params>> {
+        let parts = split_path(uri);
+
+        let mut params: Vec<Params> = vec![];
+
+        for (i, segment) in self.segments.iter().enumerate() {
+            match segment {
+                Segment::Static(s) => match parts.get(i) {
+                    Some(part) if part.eq_ignore_ascii_case(s) => {}
+                    _ => return None,
+                },
+                Segment::Param(name) => params.push(Params {
+                    key: name.clone(),
+                    value: decode_segment(parts.get(i)?)?,
+                }),
+                Segment::Wildcard(name) => {
+                    if parts.len() <= i {
+                        return None;
+                    }
+
+                    let rest = parts[i..]
+                        .iter()
+                        .map(|p| decode_segment(p))
+                        .collect::<Option<Vec<String>>>()?
+                        .join("/");
+
+                    params.push(Params {
+                        key: name.clone(),
+                        value: rest,
+                    });
+
+                    return Some(params);
+                }
+            }
+        }
+
+        match parts.len() == self.segments.len() {
+            true => Some(params),
+            false => None,
+        }
+    }
+}
+
+pub enum RouteMatch {
+    Found(Arc<Endpoint>, Vec<Params>),
+    MethodNotAllowed(Vec<Method>),
+    NotFound,
+    Misdirected,
+    UnsupportedMediaType,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum HostPattern {
+    Any,
+    Exact(String),
+    Wildcard(String),
+}
+
+impl HostPattern {
+    pub fn parse(pattern: &str) -> Self {
+        let pattern = strip_port(pattern).to_lowercase();
+
+        match pattern.as_str() {
+            "" | "*" => HostPattern::Any,
+            p => match p.strip_prefix("*.") {
+                Some(suffix) => HostPattern::Wildcard(suffix.to_string()),
+                None => HostPattern::Exact(p.to_string()),
+            },
+        }
+    }
+
+    pub fn matches(&self, host: &str) -> bool {
+        let host = strip_port(host).to_lowercase();
+
+        match self {
+            HostPattern::Any => true,
+            HostPattern::Exact(h) => &host == h,
+            HostPattern::Wildcard(suffix) => host
+                .strip_suffix(suffix.as_str())
+                .map(|sub| sub.len() > 1 && sub.ends_with('.'))
+                .unwrap_or(false),
+        }
+    }
+
+    fn specificity(&self) -> usize {
+        match self {
+            HostPattern::Any => 0,
+            HostPattern::Wildcard(suffix) => suffix.len() + 1,
+            HostPattern::Exact(h) => usize::MAX - h.len(),
+        }
+    }
+}
+
+pub fn strip_port(host: &str) -> &str {
+    let host = host.trim();
+
+    if host.starts_with('[') {
+        return match host.find(']') {
+            Some(end) => &host[..=end],
+            None => host,
+        };
+    }
+
+    match host.split_once(':') {
+        Some((h, port)) if port.chars().all(|c| c.is_ascii_digit()) => h,
+        _ => host,
+    }
+}
+
+type Candidate = (RoutePattern, Arc<Endpoint>);
+
+#[derive(Clone, Default)]
+struct Node {
+    statics: HashMap<String, Node>,
+    param: Option<Box<Node>>,
+    wildcard: Option<Box<Node>>,
+    endpoints: HashMap<Method, Vec<Candidate>>,
+}
+
+struct LookupState<'r> {
+    method: Method,
+    request: Option<&'r Request>,
+    allowed: Vec<Method>,
+    method_matched: bool,
+    unsupported_media: bool,
+    trailing_slash: Option<bool>,
+}
+
+impl<'r> LookupState<'r> {
+    fn new(method: &Method, request: Option<&'r Request>) -> Self {
+        LookupState {
+            method: method.clone(),
+            request,
+            allowed: vec![],
+            method_matched: false,
+            unsupported_media: false,
+            trailing_slash: None,
+        }
+    }
+
+    fn into_match(self, found: Option<&Candidate>, uri: &str) -> RouteMatch {
+        match found {
+            Some((pattern, endpoint)) => match pattern.matches(uri) {
+                Some(params) => RouteMatch::Found(endpoint.clone(), params),
+                None => RouteMatch::NotFound,
+            },
+            None if self.unsupported_media => RouteMatch::UnsupportedMediaType,
+            None if self.method_matched => RouteMatch::NotFound,
+            None if !self.allowed.is_empty() => RouteMatch::MethodNotAllowed(self.allowed),
+            None => RouteMatch::NotFound,
+        }
+    }
+}
+
+impl Node {
+    fn child(&mut self, segment: &Segment) -> &mut Node {
+        match segment {
+            Segment::Static(s) => self.statics.entry(s.to_lowercase()).or_default(),
+            Segment::Param(_) => self.param.get_or_insert_with(Default::default),
+            Segment::Wildcard(_) => self.wildcard.get_or_insert_with(Default::default),
+        }
+    }
+
+    fn select<'a>(&'a self, state: &mut LookupState) -> Option<&'a Candidate> {
+        let trailing_slash = state.trailing_slash;
+        let fits = |c: &&Candidate| trailing_slash.is_none_or(|t| c.0.trailing_slash == t);
+
+        for (m, candidates) in self.endpoints.iter() {
+            if !candidates.iter().any(|c| fits(&c)) {
+                continue;
+            }
+
+            if !state.allowed.contains(m) {
+                state.allowed.push(m.clone());
+            }
+
+            if *m == Method::GET && !state.allowed.contains(&Method::HEAD) {
+                state.allowed.push(Method::HEAD);
+            }
+        }
+
+        let candidates = match (self.endpoints.get(&state.method), &state.method) {
+            (Some(candidates), _) => candidates,
+            (None, Method::HEAD) => self.endpoints.get(&Method::GET)?,
+            (None, _) => return None,
+        };
+
+        state.method_matched = true;
+
+        for candidate in candidates.iter().filter(fits) {
+            let outcome = match state.request {
+                Some(request) => candidate.1.check_guards(request),
+                None => GuardOutcome::Pass,
+            };
+
+            match outcome {
+                GuardOutcome::Pass => return Some(candidate),
+                GuardOutcome::ContentType => state.unsupported_media = true,
+                GuardOutcome::Fail => {}
+            }
+        }
+
+        None
+    }
+
+    fn find_mut(&mut self, segments: &[Segment]) -> Option<&mut Node> {
+        let (first, rest) = match segments.split_first() {
+            Some(split) => split,
+            None => return Some(self),
+        };
+
+        let child = match first {
+            Segment::Static(s) => self.statics.get_mut(&s.to_lowercase()),
+            Segment::Param(_) => self.param.as_deref_mut(),
+            Segment::Wildcard(_) => return self.wildcard.as_deref_mut(),
+        };
+
+        child?.find_mut(rest)
+    }
+
+    fn lookup<'a>(&'a self, parts: &[&str], state: &mut LookupState) -> Option<&'a Candidate> {
+        let (first, rest) = match parts.split_first() {
+            Some(split) => split,
+            None => return self.select(state),
+        };
+
+        if let Some(found) = self
+            .statics
+            .get(&first.to_lowercase())
+            .and_then(|n| n.lookup(rest, state))
+        {
+            return Some(found);
+        }
+
+        if let Some(found) = self.param.as_ref().and_then(|n| n.lookup(rest, state)) {
+            return Some(found);
+        }
+
+        self.wildcard.as_ref().and_then(|n| n.select(state))
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct Router {
+    root: Node,
+    hosts: Vec<(HostPattern, Node)>,
+    names: Arc<UrlTable>,
+}
+
+impl Router {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn host_root(&mut self, host: &HostPattern) -> &mut Node {
+        if *host == HostPattern::Any {
+            return &mut self.root;
+        }
+
+        let index = match self.hosts.iter().position(|(h, _)| h == host) {
+            Some(index) => index,
+            None => {
+                self.hosts.push((host.clone(), Node::default()));
+                self.hosts
+                    .sort_by_key(|(h, _)| std::cmp::Reverse(h.specificity()));
+                self.hosts.iter().position(|(h, _)| h == host).unwrap()
+            }
+        };
+
+        &mut self.hosts[index].1
+    }
+
+    pub fn insert(&mut self, endpoint: Arc<Endpoint>) {
+        if let Err(e) = self.try_insert(endpoint) {
+            panic!("{}", e);
+        }
+    }
+
+    pub fn try_insert(&mut self, endpoint: Arc<Endpoint>) -> Result<(), String> {
+        let pattern = RoutePattern::parse(&endpoint.get_uri());
+
+        if self.route_index(&endpoint).is_some() {
+            let method_str: String = endpoint.get_method().into();
+
+            return Err(format!(
+                "Duplicate route {} with method {}",
+                pattern.get_raw(),
+                method_str
+            ));
+        }
+
+        if let Some(name) = endpoint.get_name() {
+            Arc::make_mut(&mut self.names).try_insert(&name, pattern.clone())?;
+        }
+
+        let mut node = self.host_root(&endpoint.get_host());
+
+        for segment in pattern.segments.iter() {
+            node = node.child(segment);
+
+            if let Segment::Wildcard(_) = segment {
+                break;
+            }
+        }
+
+        let candidates = node.endpoints.entry(endpoint.get_method()).or_default();
+
+        candidates.push((pattern, endpoint));
+        candidates.sort_by_key(|(_, e)| std::cmp::Reverse(e.get_guards().len()));
+
+        Ok(())
+    }
+
+    pub fn replace(&mut self, endpoint: Arc<Endpoint>) -> Result<(), String> {
+        self.remove_route(&endpoint);
+        self.try_insert(endpoint)
+    }
+
+    pub fn remove(&mut self, uri: &str, method: &Method) -> bool {
+        self.remove_matching(uri, method, &HostPattern::Any, &[])
+    }
+
+    pub fn remove_route(&mut self, endpoint: &Endpoint) -> bool {
+        self.remove_matching(
+            &endpoint.get_uri(),
+            &endpoint.get_method(),
+            &endpoint.get_host(),
+            &endpoint.get_guards(),
+        )
+    }
+
+    pub fn remove_named(&mut self, name: &str) -> bool {
+        let endpoint = self
+            .endpoints()
+            .into_iter()
+            .find(|e| e.get_name().as_deref() == Some(name));
+
+        match endpoint {
+            Some(endpoint) => self.remove_route(&endpoint),
+            None => false,
+        }
+    }
+
+    fn candidates_mut(
+        &mut self,
+        uri: &str,
+        method: &Method,
+        host: &HostPattern,
+    ) -> Option<&mut Vec<Candidate>> {
+        let pattern = RoutePattern::parse(uri);
+
+        let root = match host {
+            HostPattern::Any => Some(&mut self.root),
+            host => self
+                .hosts
+                .iter_mut()
+                .find(|(h, _)| h == host)
+                .map(|(_, n)| n),
+        };
+
+        root.and_then(|root| root.find_mut(&pattern.segments))
+            .and_then(|node| node.endpoints.get_mut(method))
+    }
+
+    fn route_index(&mut self, endpoint: &Endpoint) -> Option<usize> {
+        let guards = endpoint.get_guards();
+
+        let trailing_slash = RoutePattern::parse(&endpoint.get_uri()).trailing_slash;
+
+        self.candidates_mut(
+            &endpoint.get_uri(),
+            &endpoint.get_method(),
+            &endpoint.get_host(),
+        )?
+        .iter()
+        .position(|(p, e)| {
+            p.trailing_slash == trailing_slash && guard::same_guards(&e.get_guards(), &guards)
+        })
+    }
+
+    fn remove_matching(
+        &mut self,
+        uri: &str,
+        method: &Method,
+        host: &HostPattern,
+        guards: &[Guard],
+    ) -> bool {
+        let trailing_slash = RoutePattern::parse(uri).trailing_slash;
+
+        let candidates = match self.candidates_mut(uri, method, host) {
+            Some(candidates) => candidates,
+            None => return false,
+        };
+
+        let removed = match candidates.iter().position(|(p, e)| {
+            p.trailing_slash == trailing_slash && guard::same_guards(&e.get_guards(), guards)
+        }) {
+            Some(index) => candidates.remove(index).1,
+            None => return false,
+        };
+
+        if let Some(name) = removed.get_name() {
+            Arc::make_mut(&mut self.names).remove(&name);
+        }
+
+        true
+    }
+
+    pub fn lookup(&self, uri: &str, method: &Method) -> RouteMatch {
+        Self::lookup_node(&self.root, uri, LookupState::new(method, None))
+    }
+
+    pub fn lookup_request(&self, request: &Request, uri: &str, strict: bool) -> RouteMatch {
+        self.lookup_hosts(request, uri, strict, None)
+    }
+
+    pub fn lookup_request_exact(
+        &self,
+        request: &Request,
+        uri: &str,
+        strict: bool,
+        trailing_slash: bool,
+    ) -> RouteMatch {
+        self.lookup_hosts(request, uri, strict, Some(trailing_slash))
+    }
+
+    fn lookup_hosts(
+        &self,
+        request: &Request,
+        uri: &str,
+        strict: bool,
+        trailing_slash: Option<bool>,
+    ) -> RouteMatch {
+        let method = request.get_method();
+        let host = request.get_host();
+
+        let host_roots = self
+            .hosts
+            .iter()
+            .filter(|(h, _)| h.matches(&host))
+            .map(|(_, n)| n)
+            .collect::<Vec<_>>();
+
+        if strict && host_roots.is_empty() {
+            return RouteMatch::Misdirected;
+        }
+
+        let mut fallback = RouteMatch::NotFound;
+
+        for root in host_roots.into_iter().chain(std::iter::once(&self.root)) {
+            let state = LookupState {
+                trailing_slash,
+                ..LookupState::new(&method, Some(request))
+            };
+
+            match Self::lookup_node(root, uri, state) {
+                RouteMatch::Found(endpoint, params) => return RouteMatch::Found(endpoint, params),
+                RouteMatch::NotFound => {}
+                other => {
+                    if let RouteMatch::NotFound = fallback {
+                        fallback = other;
+                    }
+                }
+            }
+        }
+
+        fallback
+    }
+
+    fn lookup_node(root: &Node, uri: &str, mut state: LookupState) -> RouteMatch {
+        let parts = split_path(uri);
+
+        let found = root.lookup(&parts, &mut state);
+
+        state.into_match(found, uri)
+    }
+
+    pub fn get_url_table(&self) -> Arc<UrlTable> {
+        self.names.clone()
+    }
+
+    pub fn endpoints(&self) -> Vec<Arc<Endpoint>> {
+        let mut ret: Vec<Arc<Endpoint>> = vec![];
+        let mut stack: Vec<&Node> = vec![&self.root];
+
+        stack.extend(self.hosts.iter().map(|(_, n)| n));
+
+        while let Some(node) = stack.pop() {
+            ret.extend(node.endpoints.values().flatten().map(|(_, e)| e.clone()));
+            stack.extend(node.statics.values());
+            stack.extend(node.param.iter().map(|n| n.as_ref()));
+            stack.extend(node.wildcard.iter().map(|n| n.as_ref()));
+        }
+
+        ret
+    }
+}
+
+pub struct RouteGroup {
+    prefix: String,
+    host: HostPattern,
+    endpoints: Vec<Endpoint>,
+    middleware: Vec<MiddlewareRef>,
+}
+
+impl RouteGroup {
+    pub fn new(prefix: &str) -> Self {
+        RouteGroup {
+            prefix: prefix.to_string(),
+            host: HostPattern::Any,
+            endpoints: vec![],
+            middleware: vec![],
+        }
+    }
+
+    pub fn middleware<M: Middleware + 'static>(mut self, middleware: M) -> Self {
+        self.middleware.push(Arc::new(middleware));
+
+        self
+    }
+
+    pub fn host(mut self, host: &str) -> Self {
+        self.host = HostPattern::parse(host);
+
+        self
+    }
+
+    pub fn route(mut self, endpoint: Endpoint) -> Self {
+        self.endpoints.push(endpoint);
+
+        self
+    }
+
+    pub fn get<F, R>(self, uri: &str, callable: F) -> Self
+    where
+        F: Fn(&Request) -> R + Send + Sync + 'static,
+        R: IntoResponse,
+    {
+        self.route(Endpoint::new(uri.to_string(), callable, Method::GET))
+    }
+
+    pub fn post<F, R>(self, uri: &str, callable: F) -> Self
+    where
+        F: Fn(&Request) -> R + Send + Sync + 'static,
+        R: IntoResponse,
+    {
+        self.route(Endpoint::new(uri.to_string(), callable, Method::POST))
+    }
+
+    pub fn put<F, R>(self, uri: &str, callable: F) -> Self
+    where
+        F: Fn(&Request) -> R + Send + Sync + 'static,
+        R: IntoResponse,
+    {
+        self.route(Endpoint::new(uri.to_string(), callable, Method::PUT))
+    }
+
+    pub fn delete<F, R>(self, uri: &str, callable: F) -> Self
+    where
+        F: Fn(&Request) -> R + Send + Sync + 'static,
+        R: IntoResponse,
+    {
+        self.route(Endpoint::new(uri.to_string(), callable, Method::DELETE))
+    }
+
+    pub fn nest(mut self, group: RouteGroup) -> Self {
+        self.endpoints.extend(group.into_endpoints());
+
+        self
+    }
+
+    pub fn get_prefix(&self) -> String {
+        self.prefix.clone()
+    }
+
+    pub fn into_endpoints(self) -> Vec<Endpoint> {
+        let prefix = self.prefix;
+        let host = self.host;
+        let middleware = self.middleware;
+
+        self.endpoints
+            .into_iter()
+            .map(|e| match (&host, e.get_host()) {
+                (HostPattern::Any, _) | (_, HostPattern::Exact(_) | HostPattern::Wildcard(_)) => e,
+                (h, HostPattern::Any) => e.with_host(h.clone()),
+            })
+            .map(|e| e.with_outer_middleware(&middleware).with_prefix(&prefix))
+            .collect()
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TrailingSlash {
+    Strict,
+    Match,
+    Redirect,
+}
+
+#[derive(Clone)]
+pub struct PathNormalization {
+    collapse_slashes: bool,
+    resolve_dots: bool,
+    trailing_slash: TrailingSlash,
+}
+
+impl Default for PathNormalization {
+    fn default() -> Self {
+        PathNormalization {
+            collapse_slashes: true,
+            resolve_dots: true,
+            trailing_slash: TrailingSlash::Match,
+        }
+    }
+}
+
+pub struct NormalizedPath {
+    pub path: String,
+    pub trailing_slash: bool,
+}
+
+impl NormalizedPath {
+    pub fn get_redirect(&self, location: &str) -> String {
+        let path = format!("/{}", self.path.trim_start_matches(['/', '\\']));
+
+        match location.split_once('?') {
+            Some((_, query)) => format!("{}?{}", path, query),
+            None => path,
+        }
+    }
+}
+
+impl PathNormalization {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn collapse_slashes(mut self, collapse_slashes: bool) -> Self {
+        self.collapse_slashes = collapse_slashes;
+
+        self
+    }
+
+    pub fn resolve_dots(mut self, resolve_dots: bool) -> Self {
+        self.resolve_dots = resolve_dots;
+
+        self
+    }
+
+    pub fn trailing_slash(mut self, trailing_slash: TrailingSlash) -> Self {
+        self.trailing_slash = trailing_slash;
+
+        self
+    }
+
+    pub fn get_trailing_slash(&self) -> TrailingSlash {
+        self.trailing_slash
+    }
+
+    pub fn normalize(&self, uri: &str) -> Option<NormalizedPath> {
+        let trimmed = uri.trim_start_matches('/');
+        let trailing_slash = trimmed.ends_with('/');
+        let trimmed = trimmed.strip_suffix('/').unwrap_or(trimmed);
+
+        let mut segments: Vec<String> = vec![];
+
+        for segment in trimmed.split('/').map(decode_unreserved) {
+            match segment.as_str() {
+                "" if trimmed.is_empty() => {}
+                "" if self.collapse_slashes => {}
+                "" => return None,
+                "." if self.resolve_dots => {}
+                ".." if self.resolve_dots => {
+                    segments.pop();
+                }
+                _ => segments.push(segment),
+            }
+        }
+
+        let path = match segments.is_empty() {
+            true => "/".to_string(),
+            false => segments.join("/"),
+        };
+
+        Some(NormalizedPath {
+            path,
+            trailing_slash,
+        })
+    }
+}
+
+pub fn join_path(prefix: &str, uri: &str) -> String {
+    let joined = split_path(prefix)
+        .into_iter()
+        .chain(split_path(uri))
+        .collect::<Vec<&str>>()
+        .join("/");
+
+    match !joined.is_empty() && uri.ends_with('/') {
+        true => format!("/{}/", joined),
+        false => format!("/{}", joined),
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct UrlTable {
+    names: HashMap<String, RoutePattern>,
+}
+
+impl UrlTable {
+    pub fn insert(&mut self, name: &str, pattern: RoutePattern) {
+        if let Err(e) = self.try_insert(name, pattern) {
+            panic!("{}", e);
+        }
+    }
+
+    pub fn try_insert(&mut self, name: &str, pattern: RoutePattern) -> Result<(), String> {
+        if let Some(existing) = self.names.get(name) {
+            return Err(format!(
+                "Duplicate route name {} for {} and {}",
+                name,
+                existing.get_raw(),
+                pattern.get_raw()
+            ));
+        }
+
+        self.names.insert(name.to_string(), pattern);
+
+        Ok(())
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<RoutePattern> {
+        self.names.remove(name)
+    }
+
+    pub fn get(&self, name: &str) -> Option<RoutePattern> {
+        self.names.get(name).cloned()
+    }
+
+    pub fn url_for(
+        &self,
+        name: &str,
+        params: &[(&str, &str)],
+        query: &[(&str, &str)],
+    ) -> Result<String, String> {
+        let pattern = self
+            .names
+            .get(name)
+            .ok_or_else(|| format!("No route named {}", name))?;
+
+        let mut url = pattern.build(params)?;
+
+        if !query.is_empty() {
+            let query_str = query
+                .iter()
+                .map(|(k, v)| format!("{}={}", percent_encode(k), percent_encode(v)))
+                .collect::<Vec<String>>()
+                .join("&");
+
+            url = format!("{}?{}", url, query_str);
+        }
+
+        Ok(url)
+    }
+}
+
+fn is_unreserved(b: u8) -> bool {
+    matches!(b, b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~')
+}
+
+pub fn percent_encode(s: &str) -> String {
+    s.bytes()
+        .map(|b| match is_unreserved(b) {
+            true => (b as char).to_string(),
+            false => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+pub fn split_path(uri: &str) -> Vec<&str> {
+    uri.split('/').filter(|s| !s.is_empty()).collect()
+}
+
+pub fn percent_decode(s: &str) -> String {
+    decode_if(s, |_| true)
+}
+
+pub fn decode_unreserved(s: &str) -> String {
+    decode_if(s, is_unreserved)
+}
+
+pub fn decode_segment(s: &str) -> Option<String> {
+    let decoded = decode_if(s, |b| b != b'/');
+
+    match decoded.as_str() {
+        "." | ".." => None,
+        _ => Some(decoded),
+    }
+}
+
+fn decode_if<F: Fn(u8) -> bool>(s: &str, decode: F) -> String {
+    let bytes = s.as_bytes();
+
+    let mut ret: Vec<u8> = vec![];
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let decoded = match bytes[i] {
+            b'%' if i + 2 < bytes.len() => std::str::from_utf8(&bytes[i + 1..i + 3])
+                .ok()
+                .and_then(|h| u8::from_str_radix(h, 16).ok())
+                .filter(|b| decode(*b)),
+            _ => None,
+        };
+
+        match decoded {
+            Some(b) => {
+                ret.push(b);
+                i += 3;
+            }
+            None => {
+                ret.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&ret).to_string()
+}
+
+pub enum PathParamError {
+    Missing(String),
+    Invalid(String, String),
+}
+
+impl From<PathParamError> for String {
+    fn from(e: PathParamError) -> Self {
+        match e {
+            PathParamError::Missing(name) => format!("Missing path parameter: {}", name),
+            PathParamError::Invalid(name, value) => {
+                format!("Invalid value for path parameter {}: {}", name, value)
+            }
+        }
+    }
+}
+
+impl IntoResponse for PathParamError {
+    fn into_response(self) -> Response {
+        let message: String = self.into();
+
+        Response::new_string(message, MimeType::TextPlain, HttpStatus::Http404NotFound)
+    }
+}
+
+pub fn parse_param<T: FromStr>(params: &[Params], name: &str) -> Result<T, PathParamError> {
+    let value = params
+        .iter()
+        .find(|p| p.key == name)
+        .map(|p| p.value.clone())
+        .ok_or_else(|| PathParamError::Missing(name.to_string()))?;
+
+    value
+        .parse::<T>()
+        .map_err(|_| PathParamError::Invalid(name.to_string(), value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::guard::Guard;
+    use crate::middleware::Before;
+    use crate::parser::test_request;
+    use crate::response::Response;
+    use crate::samovar::{Dispatch, Samovar, Settings};
+
+    fn endpoint(uri: &str, body: &'static str) -> Endpoint {
+        Endpoint::new(uri.to_string(), move |_: &Request| body, Method::GET)
+    }
+
+    fn request(uri: &str, host: &str, headers: &[&str]) -> Request {
+        let host = format!("Host: {}", host);
+
+        test_request("GET", uri, &[&[host.as_str()], headers].concat(), "")
+    }
+
+    fn found_body(found: RouteMatch, request: &Request) -> Option<String> {
+        match found {
+            RouteMatch::Found(endpoint, _) => {
+                let body = endpoint.call(request).compose().into_bytes();
+                let body = String::from_utf8(body).unwrap();
+
+                body.rsplit("\r\n\r\n").next().map(|b| b.to_string())
+            }
+            _ => None,
+        }
+    }
+
+    fn route(router: &Router, uri: &str, host: &str, headers: &[&str]) -> Option<String> {
+        let req = request(uri, host, headers);
+
+        found_body(router.lookup_request(&req, uri, false), &req)
+    }
+
+    fn on_host(uri: &str, body: &'static str, host: &str) -> Arc<Endpoint> {
+        Arc::new(endpoint(uri, body).with_host(HostPattern::parse(host)))
+    }
+
+    #[test]
+    fn host_patterns_match_exact_and_wildcard() {
+        assert!(HostPattern::parse("API.example.com:8080").matches("api.example.com"));
+        assert!(HostPattern::parse("*.example.com").matches("api.example.com:80"));
+        assert!(!HostPattern::parse("*.example.com").matches("example.com"));
+        assert!(!HostPattern::parse("*.example.com").matches("badexample.com"));
+        assert_eq!(HostPattern::parse("*"), HostPattern::Any);
+        assert_eq!(strip_port("[::1]:8080"), "[::1]");
+    }
+
+    #[test]
+    fn wildcard_host_is_tried_after_exact_host_misses() {
+        let mut router = Router::new();
+
+        router.insert(on_host("/users", "exact", "api.example.com"));
+        router.insert(on_host("/health", "wildcard", "*.example.com"));
+        router.insert(Arc::new(endpoint("/", "root")));
+
+        assert_eq!(
+            route(&router, "/users", "api.example.com", &[]).as_deref(),
+            Some("exact")
+        );
+        assert_eq!(
+            route(&router, "/health", "api.example.com", &[]).as_deref(),
+            Some("wildcard")
+        );
+        assert_eq!(
+            route(&router, "/", "api.example.com", &[]).as_deref(),
+            Some("root")
+        );
+        assert_eq!(route(&router, "/users", "www.example.com", &[]), None);
+    }
+
+    #[test]
+    fn more_specific_host_wins() {
+        let mut router = Router::new();
+
+        router.insert(on_host("/", "wildcard", "*.example.com"));
+        router.insert(on_host("/", "exact", "api.example.com"));
+        router.insert(Arc::new(endpoint("/", "root")));
+
+        assert_eq!(
+            route(&router, "/", "api.example.com", &[]).as_deref(),
+            Some("exact")
+        );
+        assert_eq!(
+            route(&router, "/", "www.example.com", &[]).as_deref(),
+            Some("wildcard")
+        );
+        assert_eq!(
+            route(&router, "/", "other.test", &[]).as_deref(),
+            Some("root")
+        );
+    }
+
+    #[test]
+    fn method_mismatch_on_exact_host_falls_through() {
+        let mut router = Router::new();
+
+        router.insert(Arc::new(
+            Endpoint::new("/health".to_string(), |_: &Request| "post", Method::POST)
+                .with_host(HostPattern::parse("api.example.com")),
+        ));
+        router.insert(on_host("/health", "wildcard", "*.example.com"));
+
+        assert_eq!(
+            route(&router, "/health", "api.example.com", &[]).as_deref(),
+            Some("wildcard")
+        );
+
+        let req = request("/other", "api.example.com", &[]);
+        assert!(matches!(
+            router.lookup_request(&req, "/other", true),
+            RouteMatch::NotFound
+        ));
+
+        let req = request("/health", "unknown.test", &[]);
+        assert!(matches!(
+            router.lookup_request(&req, "/health", true),
+            RouteMatch::Misdirected
+        ));
+    }
+
+    #[test]
+    fn remove_keeps_host_bound_siblings() {
+        let mut router = Router::new();
+
+        router.insert(Arc::new(endpoint("/health", "any")));
+        router.insert(Arc::new(
+            endpoint("/health", "a").with_host(HostPattern::parse("a.test")),
+        ));
+
+        assert!(router.remove("/health", &Method::GET));
+        assert!(!router.remove("/health", &Method::GET));
+
+        assert_eq!(
+            route(&router, "/health", "a.test", &[]).as_deref(),
+            Some("a")
+        );
+        assert_eq!(route(&router, "/health", "other.test", &[]), None);
+    }
+
+    #[test]
+    fn remove_route_matches_guards() {
+        let mut router = Router::new();
+        let guarded = endpoint("/v", "v2").with_guard(Guard::header("X-Version", "2"));
+
+        router.insert(Arc::new(endpoint("/v", "plain")));
+        router.insert(Arc::new(guarded.clone()));
+
+        assert_eq!(
+            route(&router, "/v", "x", &["X-Version: 2"]).as_deref(),
+            Some("v2")
+        );
+
+        assert!(router.remove_route(&guarded));
+
+        assert_eq!(
+            route(&router, "/v", "x", &["X-Version: 2"]).as_deref(),
+            Some("plain")
+        );
+        assert_eq!(route(&router, "/v", "x", &[]).as_deref(), Some("plain"));
+    }
+
+    #[test]
+    fn replace_swaps_only_the_same_route() {
+        let mut router = Router::new();
+
+        router.insert(Arc::new(endpoint("/a", "one").with_name("a")));
+        router
+            .replace(Arc::new(endpoint("/a", "two").with_name("a2")))
+            .unwrap();
+
+        assert_eq!(route(&router, "/a", "x", &[]).as_deref(), Some("two"));
+        assert!(router.get_url_table().get("a").is_none());
+        assert!(router.get_url_table().get("a2").is_some());
+        assert_eq!(router.endpoints().len(), 1);
+    }
+
+    #[test]
+    fn insert_rejects_duplicate_routes() {
+        let mut router = Router::new();
+
+        router.insert(Arc::new(endpoint("/a", "one").with_name("a")));
+
+        assert_eq!(
+            router.try_insert(Arc::new(endpoint("/a", "two").with_name("a2"))),
+            Err("Duplicate route /a with method GET".to_string())
+        );
+        assert_eq!(route(&router, "/a", "x", &[]).as_deref(), Some("one"));
+        assert!(router.get_url_table().get("a2").is_none());
+
+        assert!(router
+            .try_insert(Arc::new(
+                endpoint("/a", "guarded").with_guard(Guard::query_param("q"))
+            ))
+            .is_ok());
+        assert!(router
+            .try_insert(Arc::new(
+                endpoint("/a", "host").with_host(HostPattern::parse("h.test"))
+            ))
+            .is_ok());
+    }
+
+    #[test]
+    fn add_endpoint_refuses_to_overwrite() {
+        let samovar = Samovar::new("127.0.0.1", 0);
+
+        samovar.add_endpoint(endpoint("/a", "a")).unwrap();
+
+        assert!(samovar.add_endpoint(endpoint("/a", "b")).is_err());
+        assert!(samovar.replace_endpoint(endpoint("/a", "c")).is_ok());
+        assert_eq!(
+            route(&samovar.get_routes(), "/a", "x", &[]).as_deref(),
+            Some("c")
+        );
+    }
+
+    #[test]
+    fn remove_named_removes_one_route() {
+        let mut router = Router::new();
+
+        router.insert(Arc::new(endpoint("/a", "a").with_name("a")));
+        router.insert(Arc::new(
+            endpoint("/a", "host").with_host(HostPattern::parse("h.test")),
+        ));
+
+        assert!(router.remove_named("a"));
+        assert!(!router.remove_named("a"));
+
+        assert_eq!(route(&router, "/a", "h.test", &[]).as_deref(), Some("host"));
+        assert_eq!(route(&router, "/a", "x.test", &[]), None);
+    }
+
+    #[test]
+    fn failed_replace_leaves_routes_untouched() {
+        let samovar = Samovar::new("127.0.0.1", 0);
+
+        samovar
+            .add_endpoint(endpoint("/a", "a").with_name("a"))
+            .unwrap();
+        samovar
+            .add_endpoint(endpoint("/b", "b").with_name("b"))
+            .unwrap();
+
+        assert!(samovar
+            .replace_endpoint(endpoint("/a", "new").with_name("b"))
+            .is_err());
+
+        let routes = samovar.get_routes();
+
+        assert_eq!(route(&routes, "/a", "x", &[]).as_deref(), Some("a"));
+        assert!(routes.get_url_table().get("a").is_some());
+    }
+
+    fn normalized(normalization: &PathNormalization, uri: &str) -> Option<String> {
+        normalization.normalize(uri).map(|n| n.path)
+    }
+
+    fn dispatch_status(normalization: PathNormalization, uri: &str) -> HttpStatus {
+        let mut samovar = Samovar::new("127.0.0.1", 0);
+
+        samovar.set_path_normalization(normalization);
+        samovar.add_endpoint(endpoint("/a/b", "b")).unwrap();
+
+        match samovar.dispatcher().dispatch(request(uri, "x", &[])) {
+            Dispatch::Endpoint(endpoint, request) => endpoint.call(&request).get_status(),
+            Dispatch::Respond(resp) => resp.get_status(),
+        }
+    }
+
+    #[test]
+    fn normalize_collapses_slashes_and_resolves_dots() {
+        let normalization = PathNormalization::new();
+
+        assert_eq!(normalized(&normalization, "/").as_deref(), Some("/"));
+        assert_eq!(normalized(&normalization, "//a//b").as_deref(), Some("a/b"));
+        assert_eq!(
+            normalized(&normalization, "a/./b/../c").as_deref(),
+            Some("a/c")
+        );
+        assert_eq!(normalized(&normalization, "../../a").as_deref(), Some("a"));
+        assert_eq!(normalized(&normalization, "a/..").as_deref(), Some("/"));
+
+        let strict = PathNormalization::new()
+            .collapse_slashes(false)
+            .resolve_dots(false);
+
+        assert_eq!(normalized(&strict, "a//b"), None);
+        assert_eq!(normalized(&strict, "a/./b/..").as_deref(), Some("a/./b/.."));
+    }
+
+    #[test]
+    fn normalize_decodes_escaped_dots_before_resolving() {
+        let normalization = PathNormalization::new();
+
+        assert_eq!(
+            normalized(&normalization, "a/%2e%2e/%2E%2E/etc%2fpasswd").as_deref(),
+            Some("etc%2fpasswd")
+        );
+        assert_eq!(
+            normalized(&normalization, "a/%2e/b").as_deref(),
+            Some("a/b")
+        );
+        assert_eq!(
+            normalized(&normalization, "%7euser/%41").as_deref(),
+            Some("~user/A")
+        );
+        assert_eq!(
+            normalized(&normalization, "a%20b/%2F").as_deref(),
+            Some("a%20b/%2F")
+        );
+    }
+
+    #[test]
+    fn normalize_reports_trailing_slashes() {
+        let normalization = PathNormalization::new();
+
+        let found = normalization.normalize("a/b/").unwrap();
+        assert!(found.trailing_slash);
+        assert_eq!(found.get_redirect("/a/b/?x=1"), "/a/b?x=1");
+
+        assert!(!normalization.normalize("a/b").unwrap().trailing_slash);
+        assert!(!normalization.normalize("/").unwrap().trailing_slash);
+    }
+
+    #[test]
+    fn trailing_slash_policy_applies_on_dispatch() {
+        let policy = |t| PathNormalization::new().trailing_slash(t);
+
+        assert!(matches!(
+            dispatch_status(policy(TrailingSlash::Match), "/a/b/"),
+            HttpStatus::Http200Ok
+        ));
+        assert!(matches!(
+            dispatch_status(policy(TrailingSlash::Strict), "/a/b/"),
+            HttpStatus::Http404NotFound
+        ));
+        assert!(matches!(
+            dispatch_status(policy(TrailingSlash::Redirect), "/a/b/"),
+            HttpStatus::Http308PermanentRedirect
+        ));
+        assert!(matches!(
+            dispatch_status(policy(TrailingSlash::Strict), "/a/b"),
+            HttpStatus::Http200Ok
+        ));
+    }
+
+    fn strict_samovar() -> Samovar {
+        let mut samovar = Samovar::new("127.0.0.1", 0);
+
+        samovar
+            .set_path_normalization(PathNormalization::new().trailing_slash(TrailingSlash::Strict));
+
+        for (uri, body) in [("/a/b", "b"), ("/dir/", "dir"), ("/c", "c"), ("/c/", "c/")] {
+            samovar.add_endpoint(endpoint(uri, body)).unwrap();
+        }
+
+        samovar
+    }
+
+    fn strict_body(samovar: &Samovar, method: &str, uri: &str) -> Option<String> {
+        let request = test_request(method, uri, &["Host: x"], "");
+
+        match samovar.dispatcher().dispatch(request) {
+            Dispatch::Endpoint(endpoint, request) => {
+                let mut resp = endpoint.call(&request);
+                let body = String::from_utf8(resp.compose().into_bytes()).unwrap();
+
+                match resp.get_status() {
+                    HttpStatus::Http200Ok => body.rsplit("\r\n\r\n").next().map(|b| b.to_string()),
+                    _ => None,
+                }
+            }
+            Dispatch::Respond(resp) => {
+                let status: String = resp.get_status().into();
+
+                Some(status)
+            }
+        }
+    }
+
+    #[test]
+    fn strict_mode_matches_trailing_slashes_exactly() {
+        let samovar = strict_samovar();
+
+        assert_eq!(
+            strict_body(&samovar, "GET", "/dir/").as_deref(),
+            Some("dir")
+        );
+        assert_eq!(strict_body(&samovar, "GET", "/dir"), None);
+        assert_eq!(strict_body(&samovar, "POST", "/dir"), None);
+        assert_eq!(strict_body(&samovar, "GET", "/a/b"), Some("b".to_string()));
+        assert_eq!(strict_body(&samovar, "GET", "/a/b/"), None);
+        assert_eq!(strict_body(&samovar, "GET", "/c").as_deref(), Some("c"));
+        assert_eq!(strict_body(&samovar, "GET", "/c/").as_deref(), Some("c/"));
+        assert_eq!(
+            strict_body(&samovar, "POST", "/dir/").as_deref(),
+            Some("405 Method Not Allowed")
+        );
+    }
+
+    #[test]
+    fn trailing_slash_routes_still_match_loosely() {
+        let samovar = Samovar::new("127.0.0.1", 0);
+
+        samovar.add_endpoint(endpoint("/dir/", "dir")).unwrap();
+
+        assert_eq!(strict_body(&samovar, "GET", "/dir").as_deref(), Some("dir"));
+        assert_eq!(
+            strict_body(&samovar, "GET", "/dir/").as_deref(),
+            Some("dir")
+        );
+    }
+
+    #[test]
+    fn urls_keep_declared_trailing_slashes() {
+        let mut table = UrlTable::default();
+
+        table.insert("dir", RoutePattern::parse("/files/:name/"));
+
+        assert_eq!(
+            table.url_for("dir", &[("name", "a")], &[("q", "1")]),
+            Ok("/files/a/?q=1".to_string())
+        );
+        assert_eq!(join_path("/api", "/dir/"), "/api/dir/");
+        assert_eq!(join_path("/api/", ""), "/api");
+    }
+
+    #[test]
+    fn redirects_stay_on_the_same_origin() {
+        let normalization = PathNormalization::new();
+
+        for uri in [
+            "//evil.com/",
+            "/\\evil.com/",
+            "\\\\evil.com/",
+            "/%5Cevil.com/",
+        ] {
+            let location = normalization.normalize(uri).unwrap().get_redirect(uri);
+
+            assert!(location.starts_with('/'), "{}", location);
+            assert!(!location.starts_with("//"), "{}", location);
+            assert!(!location.starts_with("/\\"), "{}", location);
+        }
+
+        let escaped = NormalizedPath {
+            path: "\\/\\evil.com".to_string(),
+            trailing_slash: true,
+        };
+
+        assert_eq!(escaped.get_redirect("/x/?a=1"), "/evil.com?a=1");
+    }
+
+    #[test]
+    fn captured_segments_cannot_escape() {
+        let pattern = RoutePattern::parse("/a/*rest");
+
+        assert!(matches!(
+            dispatch_status(PathNormalization::new(), "/a/%2e%2e/%2E%2E/etc%2fpasswd"),
+            HttpStatus::Http404NotFound
+        ));
+
+        let params = pattern.matches("a/..%2f..%2fetc/x%20y").unwrap();
+        assert_eq!(params[0].value, "..%2f..%2fetc/x y");
+
+        assert!(pattern.matches("a/b/%2e%2e").is_none());
+        assert!(RoutePattern::parse("/f/:name").matches("f/%2E").is_none());
+    }
+
+    fn captured(pattern: &str, uri: &str) -> Option<Vec<(String, String)>> {
+        RoutePattern::parse(pattern)
+            .matches(uri)
+            .map(|params| params.into_iter().map(|p| (p.key, p.value)).collect())
+    }
+
+    fn param(key: &str, value: &str) -> (String, String) {
+        (key.to_string(), value.to_string())
+    }
+
+    #[test]
+    fn captures_path_parameters() {
+        assert_eq!(
+            captured("/users/:id/posts/:post", "users/42/posts/hello%20world"),
+            Some(vec![param("id", "42"), param("post", "hello world")])
+        );
+        assert_eq!(
+            captured("/Users/:id", "users/7"),
+            Some(vec![param("id", "7")])
+        );
+        assert_eq!(captured("/users/:id", "users"), None);
+        assert_eq!(captured("/users/:id", "users/1/extra"), None);
+        assert_eq!(captured("/users/:id", "posts/1"), None);
+    }
+
+    #[test]
+    fn captures_wildcard_tails() {
+        assert_eq!(
+            captured("/static/*path", "static/css/site%20main.css"),
+            Some(vec![param("path", "css/site main.css")])
+        );
+        assert_eq!(
+            captured("/a/:b/*rest", "a/1/2/3"),
+            Some(vec![param("b", "1"), param("rest", "2/3")])
+        );
+        assert_eq!(captured("/static/*path", "static"), None);
+    }
+
+    #[test]
+    fn parses_typed_path_parameters() {
+        let params = vec![Params {
+            key: "id".to_string(),
+            value: "42".to_string(),
+        }];
+
+        assert_eq!(parse_param::<u32>(&params, "id").ok(), Some(42));
+        assert!(matches!(
+            parse_param::<u32>(&params, "missing"),
+            Err(PathParamError::Missing(name)) if name == "missing"
+        ));
+
+        let params = vec![Params {
+            key: "id".to_string(),
+            value: "abc".to_string(),
+        }];
+
+        assert!(matches!(
+            parse_param::<u32>(&params, "id"),
+            Err(PathParamError::Invalid(name, value)) if name == "id" && value == "abc"
+        ));
+    }
+
+    fn looked_up(router: &Router, uri: &str, method: Method) -> Option<String> {
+        let req = request(uri, "x", &[]);
+
+        found_body(router.lookup(uri, &method), &req)
+    }
+
+    fn allowed(router: &Router, uri: &str, method: Method) -> Option<Vec<String>> {
+        match router.lookup(uri, &method) {
+            RouteMatch::MethodNotAllowed(allowed) => {
+                let mut allowed = allowed
+                    .into_iter()
+                    .map(|m| m.into())
+                    .collect::<Vec<String>>();
+
+                allowed.sort();
+
+                Some(allowed)
+            }
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn static_beats_param_beats_wildcard() {
+        let mut router = Router::new();
+
+        router.insert(Arc::new(endpoint("/files/*path", "wildcard")));
+        router.insert(Arc::new(endpoint("/files/:name", "param")));
+        router.insert(Arc::new(endpoint("/files/readme", "static")));
+
+        assert_eq!(
+            looked_up(&router, "/files/readme", Method::GET).as_deref(),
+            Some("static")
+        );
+        assert_eq!(
+            looked_up(&router, "/files/other", Method::GET).as_deref(),
+            Some("param")
+        );
+        assert_eq!(
+            looked_up(&router, "/files/a/b", Method::GET).as_deref(),
+            Some("wildcard")
+        );
+    }
+
+    #[test]
+    fn backtracks_out_of_dead_end_branches() {
+        let mut router = Router::new();
+
+        router.insert(Arc::new(endpoint("/users/me", "me")));
+        router.insert(Arc::new(endpoint("/users/:id/posts", "posts")));
+        router.insert(Arc::new(endpoint("/*rest", "fallback")));
+
+        assert_eq!(
+            looked_up(&router, "/users/me/posts", Method::GET).as_deref(),
+            Some("posts")
+        );
+        assert_eq!(
+            looked_up(&router, "/users/me/likes", Method::GET).as_deref(),
+            Some("fallback")
+        );
+
+        match router.lookup("/users/7/posts", &Method::GET) {
+            RouteMatch::Found(_, params) => assert_eq!(params[0].value, "7"),
+            _ => panic!("expected a match"),
+        }
+    }
+
+    #[test]
+    fn distinguishes_not_found_from_method_not_allowed() {
+        let mut router = Router::new();
+
+        router.insert(Arc::new(endpoint("/items", "list")));
+        router.insert(Arc::new(Endpoint::new(
+            "/items".to_string(),
+            |_: &Request| "create",
+            Method::POST,
+        )));
+
+        assert_eq!(
+            looked_up(&router, "/items", Method::HEAD).as_deref(),
+            Some("list")
+        );
+        assert_eq!(
+            allowed(&router, "/items", Method::DELETE),
+            Some(vec![
+                "GET".to_string(),
+                "HEAD".to_string(),
+                "POST".to_string()
+            ])
+        );
+        assert!(matches!(
+            router.lookup("/missing", &Method::DELETE),
+            RouteMatch::NotFound
+        ));
+        assert!(matches!(
+            router.lookup("/items/1", &Method::GET),
+            RouteMatch::NotFound
+        ));
+    }
+
+    #[test]
+    fn method_match_elsewhere_wins_over_405() {
+        let mut router = Router::new();
+
+        router.insert(Arc::new(Endpoint::new(
+            "/a/b".to_string(),
+            |_: &Request| "post",
+            Method::POST,
+        )));
+        router.insert(Arc::new(endpoint("/a/:x", "param")));
+
+        assert_eq!(
+            looked_up(&router, "/a/b", Method::GET).as_deref(),
+            Some("param")
+        );
+        assert_eq!(
+            allowed(&router, "/a/b", Method::PUT),
+            Some(vec![
+                "GET".to_string(),
+                "HEAD".to_string(),
+                "POST".to_string()
+            ])
+        );
+    }
+
+    fn url_table() -> UrlTable {
+        let mut table = UrlTable::default();
+
+        table.insert("user", RoutePattern::parse("/users/:id"));
+        table.insert("file", RoutePattern::parse("/static/*path"));
+        table.insert("home", RoutePattern::parse("/"));
+
+        table
+    }
+
+    #[test]
+    fn builds_urls_from_params() {
+        let table = url_table();
+
+        assert_eq!(
+            table.url_for("user", &[("id", "42")], &[]),
+            Ok("/users/42".to_string())
+        );
+        assert_eq!(table.url_for("home", &[], &[]), Ok("/".to_string()));
+        assert_eq!(
+            table.url_for("user", &[("id", "a b/c")], &[]),
+            Ok("/users/a%20b%2Fc".to_string())
+        );
+    }
+
+    #[test]
+    fn builds_wildcard_tails_segment_by_segment() {
+        let table = url_table();
+
+        assert_eq!(
+            table.url_for("file", &[("path", "css/main site.css")], &[]),
+            Ok("/static/css/main%20site.css".to_string())
+        );
+        assert_eq!(
+            table.url_for("file", &[("path", "/a//b/")], &[]),
+            Ok("/static/a/b".to_string())
+        );
+    }
+
+    #[test]
+    fn encodes_query_strings() {
+        let table = url_table();
+
+        assert_eq!(
+            table.url_for("user", &[("id", "1")], &[("q", "a&b=c"), ("x y", "é")]),
+            Ok("/users/1?q=a%26b%3Dc&x%20y=%C3%A9".to_string())
+        );
+    }
+
+    #[test]
+    fn reports_missing_params_and_names() {
+        let table = url_table();
+
+        assert_eq!(
+            table.url_for("user", &[("other", "1")], &[]),
+            Err("Missing parameter id for /users/:id".to_string())
+        );
+        assert_eq!(
+            table.url_for("nope", &[], &[]),
+            Err("No route named nope".to_string())
+        );
+        assert_eq!(
+            RoutePattern::parse("/static/*path").build(&[]),
+            Err("Missing parameter path for /static/*path".to_string())
+        );
+    }
+
+    type Trace = Vec<&'static str>;
+
+    fn tag(name: &'static str) -> Before<impl Fn(&mut Request) -> Option<Response>> {
+        Before(move |r: &mut Request| {
+            match r.extensions_mut().get_mut::<Trace>() {
+                Some(trace) => trace.push(name),
+                None => {
+                    r.extensions_mut().insert(vec![name]);
+                }
+            }
+
+            None
+        })
+    }
+
+    fn traced(uri: &str) -> Endpoint {
+        Endpoint::new(
+            uri.to_string(),
+            |r: &Request| match r.extensions().get::<Trace>() {
+                Some(trace) => trace.join(">"),
+                None => String::new(),
+            },
+            Method::GET,
+        )
+    }
+
+    fn served(samovar: &Samovar, uri: &str, host: &str) -> Option<String> {
+        let mut req = request(uri, host, &[]);
+
+        match samovar.get_routes().lookup_request(&req, uri, false) {
+            RouteMatch::Found(endpoint, _) => {
+                let mut resp = endpoint.respond(&mut req, &Settings::default());
+                let body = String::from_utf8(resp.compose().into_bytes()).unwrap();
+
+                body.rsplit("\r\n\r\n").next().map(|b| b.to_string())
+            }
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn groups_join_prefixes() {
+        let samovar = Samovar::new("127.0.0.1", 0);
+
+        samovar.mount(
+            RouteGroup::new("/api/")
+                .get("/users", |_: &Request| "users")
+                .get("", |_: &Request| "index"),
+        );
+
+        assert_eq!(
+            served(&samovar, "/api/users", "x").as_deref(),
+            Some("users")
+        );
+        assert_eq!(served(&samovar, "/api", "x").as_deref(), Some("index"));
+        assert_eq!(served(&samovar, "/users", "x"), None);
+    }
+
+    #[test]
+    fn nested_groups_stack_prefixes_and_middleware() {
+        let samovar = Samovar::new("127.0.0.1", 0);
+
+        samovar.mount(
+            RouteGroup::new("/api").middleware(tag("api")).nest(
+                RouteGroup::new("/v1")
+                    .middleware(tag("v1"))
+                    .route(traced("/users").with_middleware(tag("route"))),
+            ),
+        );
+
+        assert_eq!(
+            served(&samovar, "/api/v1/users", "x").as_deref(),
+            Some("api>v1>route")
+        );
+        assert_eq!(served(&samovar, "/v1/users", "x"), None);
+    }
+
+    #[test]
+    fn groups_lend_their_host_to_unbound_routes() {
+        let samovar = Samovar::new("127.0.0.1", 0);
+
+        samovar.mount(
+            RouteGroup::new("/")
+                .host("api.test")
+                .get("/a", |_: &Request| "group host")
+                .route(endpoint("/b", "own host").with_host(HostPattern::parse("b.test"))),
+        );
+
+        assert_eq!(
+            served(&samovar, "/a", "api.test").as_deref(),
+            Some("group host")
+        );
+        assert_eq!(served(&samovar, "/a", "other.test"), None);
+        assert_eq!(
+            served(&samovar, "/b", "b.test").as_deref(),
+            Some("own host")
+        );
+        assert_eq!(served(&samovar, "/b", "api.test"), None);
+    }
+
+    #[test]
+    fn mounted_apps_keep_their_middleware_and_state() {
+        let mut app = Samovar::new("127.0.0.1", 0);
+
+        app.add_middleware(tag("app")).unwrap();
+        app.insert_state(7u32).unwrap();
+        app.add_endpoint(traced("/t").with_middleware(tag("route")))
+            .unwrap();
+
+        let mut samovar = Samovar::new("127.0.0.1", 0);
+
+        samovar.mount_app("/sub", app);
+
+        assert_eq!(
+            served(&samovar, "/sub/t", "x").as_deref(),
+            Some("app>route")
+        );
+        assert_eq!(served(&samovar, "/t", "x"), None);
+        assert_eq!(*samovar.get_state::<u32>().unwrap(), 7);
+    }
+}
@@ -1,152 +1,771 @@
 use crate::{
-    common::{DummyResponseType, HttpStatus, Method, MimeType, ResponseTextWrapper},
+    common::{HttpStatus, Method, MimeType},
+    compression::CompressionConfig,
+    conditional::ETagKind,
     endpoint::Endpoint,
+    eventloop::EventLoop,
+    middleware::{self, Middleware, MiddlewareRef},
+    openapi::{self, OpenApiConfig},
+    parser::{ParseError, DEFAULT_MAX_BODY_SIZE, DEFAULT_READ_TIMEOUT},
+    pool::{PoolConfig, PoolStats, ThreadPool},
     request::Request,
-    response::Response,
+    response::{IntoResponse, Redirect, Response},
+    router::{join_path, PathNormalization, RouteGroup, RouteMatch, Router, TrailingSlash},
+    state::StateMap,
 };
-use std::{collections::HashMap, net::TcpListener, vec};
+use std::net::{TcpListener, TcpStream};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Engine {
+    #[default]
+    Threaded,
+    EventLoop,
+    #[cfg(feature = "async")]
+    Async,
+}
+
+#[derive(Clone)]
+pub struct Settings {
+    pub compression: Option<CompressionConfig>,
+    pub max_body_size: usize,
+    pub read_timeout: Duration,
+    pub auto_etag: Option<ETagKind>,
+    pub normalization: PathNormalization,
+    pub strict_hosts: bool,
+    pub middleware: Vec<MiddlewareRef>,
+    pub pool: PoolConfig,
+    pub engine: Engine,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            compression: None,
+            max_body_size: DEFAULT_MAX_BODY_SIZE,
+            read_timeout: DEFAULT_READ_TIMEOUT,
+            auto_etag: None,
+            normalization: PathNormalization::default(),
+            strict_hosts: false,
+            middleware: vec![],
+            pool: PoolConfig::default(),
+            engine: Engine::default(),
+        }
+    }
+}
 
 pub struct Samovar {
     port: u32,
     address: &'static str,
-    endpoints: HashMap<String, Box<Endpoint>>,
-    bare_paths: Vec<String>,
+    routes: Arc<RwLock<Arc<Router>>>,
+    settings: Settings,
+    state: Arc<StateMap>,
+    pool_stats: Arc<PoolStats>,
+    started: Arc<AtomicBool>,
 }
 
 impl Samovar {
     pub fn new(address: &'static str, port: u32) -> Self {
-        let endpoints = HashMap::<String, Box<Endpoint>>::new();
-        let bare_paths: Vec<String> = vec![];
+        let routes = Arc::new(RwLock::new(Arc::new(Router::new())));
+        let settings = Settings::default();
         Samovar {
             address,
             port,
-            endpoints,
-            bare_paths,
+            routes,
+            settings,
+            state: Arc::new(StateMap::new()),
+            pool_stats: Arc::new(PoolStats::default()),
+            started: Arc::new(AtomicBool::new(false)),
         }
     }
 
-    pub fn insert_endpoint(&mut self, endpoint: Box<Endpoint>) {
-        let ep_name = endpoint.get_uri();
+    pub fn set_compression(&mut self, config: CompressionConfig) {
+        self.settings.compression = Some(config);
+    }
 
-        let mut ep_name_chars = ep_name.chars().collect::<Vec<char>>();
+    pub fn set_auto_etag(&mut self, kind: ETagKind) {
+        self.settings.auto_etag = Some(kind);
+    }
 
-        if ep_name_chars.len() > 2 {
-            if &ep_name_chars[0] == &'/' {
-                ep_name_chars.remove(0);
-            }
+    pub fn set_max_body_size(&mut self, max_body_size: usize) {
+        self.settings.max_body_size = max_body_size;
+    }
 
-            if &ep_name_chars.last().unwrap() == &&'/' {
-                ep_name_chars.pop();
-            }
+    pub fn set_read_timeout(&mut self, read_timeout: Duration) {
+        self.settings.read_timeout = read_timeout;
+    }
+
+    pub fn set_path_normalization(&mut self, normalization: PathNormalization) {
+        self.settings.normalization = normalization;
+    }
+
+    pub fn set_strict_hosts(&mut self, strict_hosts: bool) {
+        self.settings.strict_hosts = strict_hosts;
+    }
+
+    pub fn set_pool_config(&mut self, config: PoolConfig) {
+        self.settings.pool = config;
+    }
+
+    pub fn set_engine(&mut self, engine: Engine) {
+        self.settings.engine = engine;
+    }
+
+    pub fn get_engine(&self) -> Engine {
+        self.settings.engine
+    }
+
+    pub fn get_pool_stats(&self) -> Arc<PoolStats> {
+        self.pool_stats.clone()
+    }
+
+    pub fn queue_depth(&self) -> usize {
+        self.pool_stats.queue_depth()
+    }
+
+    pub fn is_started(&self) -> bool {
+        self.started.load(Ordering::SeqCst)
+    }
+
+    pub fn add_middleware<M: Middleware + 'static>(&mut self, middleware: M) -> Result<(), String> {
+        if self.is_started() {
+            return Err("Middleware cannot be added after the server has started".to_string());
         }
 
-        let ep_name = String::from_iter(ep_name_chars.iter());
+        self.settings.middleware.push(Arc::new(middleware));
 
-        self.bare_paths.push(ep_name.clone());
+        Ok(())
+    }
 
-        let method_str: String = endpoint.get_method().into();
+    pub fn insert_state<T: Send + Sync + 'static>(&mut self, value: T) -> Result<(), String> {
+        if self.is_started() {
+            return Err("State cannot be inserted after the server has started".to_string());
+        }
 
-        let key = format!("{}_{}", ep_name, method_str);
+        Arc::make_mut(&mut self.state).insert(value);
 
-        self.endpoints.insert(key, endpoint);
+        Ok(())
     }
 
-    fn construct_response_404(&mut self) {
-        fn response_empty_handler(_: &Request) -> ResponseTextWrapper {
-            let temp_lock = crate::common::TEMP_404.lock().unwrap();
-            let temp = temp_lock.get_temp();
+    pub fn get_state<T: Send + Sync + 'static>(&self) -> Option<Arc<T>> {
+        self.state.get::<T>()
+    }
 
-            let mut resp = Response::<DummyResponseType>::new_string(
-                temp,
-                MimeType::TextPlain,
-                HttpStatus::Http404NotFound,
-            );
+    pub fn get_state_map(&self) -> Arc<StateMap> {
+        self.state.clone()
+    }
+
+    pub fn get_routes(&self) -> Arc<Router> {
+        self.routes.read().unwrap().clone()
+    }
+
+    fn update_routes<T, E, F>(&self, f: F) -> Result<T, E>
+    where
+        F: FnOnce(&mut Router) -> Result<T, E>,
+    {
+        let mut routes = self.routes.write().unwrap();
+
+        let mut updated = routes.as_ref().clone();
+        let ret = f(&mut updated)?;
+
+        *routes = Arc::new(updated);
+
+        Ok(ret)
+    }
+
+    pub fn insert_endpoint(&self, endpoint: Box<Endpoint>) {
+        if let Err(e) = self.add_endpoint(*endpoint) {
+            panic!("{}", e);
+        }
+    }
+
+    pub fn add_endpoint(&self, endpoint: Endpoint) -> Result<(), String> {
+        self.update_routes(|router| router.try_insert(Arc::new(endpoint)))
+    }
+
+    pub fn replace_endpoint(&self, endpoint: Endpoint) -> Result<(), String> {
+        self.update_routes(|router| router.replace(Arc::new(endpoint)))
+    }
+
+    pub fn remove_endpoint(&self, uri: &str, method: Method) -> bool {
+        self.update_routes(|router| match router.remove(uri, &method) {
+            true => Ok(()),
+            false => Err(()),
+        })
+        .is_ok()
+    }
+
+    pub fn remove_named_endpoint(&self, name: &str) -> bool {
+        self.update_routes(|router| match router.remove_named(name) {
+            true => Ok(()),
+            false => Err(()),
+        })
+        .is_ok()
+    }
 
-            resp.compose()
+    pub fn mount(&self, group: RouteGroup) {
+        for endpoint in group.into_endpoints() {
+            self.insert_endpoint(Box::new(endpoint));
         }
+    }
+
+    pub fn mount_app(&mut self, prefix: &str, app: Samovar) {
+        Arc::make_mut(&mut self.state).merge(&app.state);
+
+        for endpoint in app.get_routes().endpoints() {
+            let endpoint = endpoint
+                .as_ref()
+                .clone()
+                .with_outer_middleware(&app.settings.middleware)
+                .with_prefix(prefix);
 
-        let ep = Endpoint::new("404".to_string(), &response_empty_handler, Method::GET);
+            self.insert_endpoint(Box::new(endpoint));
+        }
+    }
 
-        self.insert_endpoint(Box::new(ep));
+    pub fn url_for(
+        &self,
+        name: &str,
+        params: &[(&str, &str)],
+        query: &[(&str, &str)],
+    ) -> Result<String, String> {
+        self.get_routes()
+            .get_url_table()
+            .url_for(name, params, query)
     }
 
-    fn construct_response_405(&mut self) {
-        fn response_empty_handler(_: &Request) -> ResponseTextWrapper {
+    pub fn openapi(&self, config: &OpenApiConfig) -> serde_json::Value {
+        openapi::generate(config, &self.get_routes().endpoints())
+    }
+
+    pub fn serve_openapi(&self, config: OpenApiConfig) {
+        let path = config.get_path();
+        let spec_url = join_path(&path, "openapi.json");
+
+        let json_routes = self.routes.clone();
+        let json_config = config.clone();
+
+        let json_handler = move |_: &Request| {
+            let endpoints = json_routes.read().unwrap().endpoints();
+
+            Response::new_bytes(
+                openapi::to_json(&openapi::generate(&json_config, &endpoints)).into_bytes(),
+                MimeType::ApplicationJson,
+                HttpStatus::Http200Ok,
+            )
+        };
+
+        let yaml_routes = self.routes.clone();
+        let yaml_config = config.clone();
+
+        let yaml_handler = move |_: &Request| {
+            let endpoints = yaml_routes.read().unwrap().endpoints();
+
+            Response::new_string(
+                openapi::to_yaml(&openapi::generate(&yaml_config, &endpoints)),
+                MimeType::Other("application/yaml".to_string()),
+                HttpStatus::Http200Ok,
+            )
+        };
+
+        let page = openapi::docs_page(&config, &spec_url);
+
+        let page_handler = move |_: &Request| {
+            Response::new_string(page.clone(), MimeType::TextHtml, HttpStatus::Http200Ok)
+        };
+
+        let endpoints = vec![
+            Endpoint::new(spec_url, json_handler, Method::GET),
+            Endpoint::new(join_path(&path, "openapi.yaml"), yaml_handler, Method::GET),
+            Endpoint::new(path, page_handler, Method::GET),
+        ];
+
+        for endpoint in endpoints {
+            self.insert_endpoint(Box::new(endpoint.hidden()));
+        }
+    }
+
+    fn construct_response_404() -> Arc<Endpoint> {
+        fn response_empty_handler(_: &Request) -> Response {
+            let temp_lock = crate::common::TEMP_404.lock().unwrap();
+            let temp = temp_lock.get_temp();
+
+            Response::new_string(temp, MimeType::TextPlain, HttpStatus::Http404NotFound)
+        }
+
+        Arc::new(Endpoint::new(
+            "404".to_string(),
+            response_empty_handler,
+            Method::GET,
+        ))
+    }
+
+    fn construct_response_405() -> Arc<Endpoint> {
+        fn response_empty_handler(_: &Request) -> Response {
             let temp_lock = crate::common::TEMP_405.lock().unwrap();
             let temp = temp_lock.get_temp();
 
-            let mut resp = Response::<DummyResponseType>::new_string(
+            Response::new_string(
                 temp,
                 MimeType::TextPlain,
-                HttpStatus::Http404NotFound,
-            );
+                HttpStatus::Http405MethodNotAllwed,
+            )
+        }
+
+        Arc::new(Endpoint::new(
+            "405".to_string(),
+            response_empty_handler,
+            Method::GET,
+        ))
+    }
 
-            resp.compose()
+    pub fn print_endpoint_uris(&self) {
+        for e in self.get_routes().endpoints() {
+            let method_str: String = e.get_method().into();
+
+            println!("Endpoint URI: {} -> {}", e.get_uri(), method_str);
+        }
+    }
+
+    pub fn dispatcher(&self) -> Dispatcher {
+        Dispatcher {
+            routes: self.routes.clone(),
+            state: self.state.clone(),
+            settings: Arc::new(self.settings.clone()),
+            ep_404: Self::construct_response_404(),
+            ep_405: Self::construct_response_405(),
         }
+    }
 
-        let ep = Endpoint::new("405".to_string(), &response_empty_handler, Method::GET);
+    pub fn server(&self) -> Server {
+        self.started.store(true, Ordering::SeqCst);
 
-        self.insert_endpoint(Box::new(ep));
+        Server {
+            bind: format!("{}:{}", self.address, self.port),
+            dispatcher: self.dispatcher(),
+            pool_stats: self.pool_stats.clone(),
+        }
     }
 
-    pub fn print_endpoint_uris(&self) {
-        for (s, e) in &self.endpoints {
-            println!("Endpoint URI: {} -> {}", s, e.get_uri());
+    pub fn run(&self) {
+        self.server().run()
+    }
+
+    #[cfg(feature = "async")]
+    pub async fn serve(&self) {
+        self.server().serve().await
+    }
+}
+
+pub struct Server {
+    bind: String,
+    dispatcher: Dispatcher,
+    pool_stats: Arc<PoolStats>,
+}
+
+impl Server {
+    pub fn run(&self) {
+        match self.dispatcher.get_settings().engine {
+            Engine::Threaded => self.run_threaded(),
+            Engine::EventLoop => self.run_event_loop(),
+            #[cfg(feature = "async")]
+            Engine::Async => self.run_async(),
         }
     }
 
-    pub fn run(&mut self) {
-        self.construct_response_404();
-        self.construct_response_405();
+    #[cfg(feature = "async")]
+    fn run_async(&self) {
+        let workers = self.dispatcher.get_settings().pool.get_workers();
 
-        let bind = format!("{}:{}", self.address, self.port);
+        tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(workers)
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(self.serve())
+    }
 
-        println!("Starting server on: {}", &bind);
+    #[cfg(feature = "async")]
+    pub async fn serve(&self) {
+        crate::asyncserver::serve(
+            self.bind.clone(),
+            self.dispatcher.clone(),
+            self.pool_stats.clone(),
+        )
+        .await
+    }
 
-        let listener = TcpListener::bind(bind).unwrap();
+    fn run_event_loop(&self) {
+        let dispatcher = self.dispatcher.clone();
+        let settings = dispatcher.get_settings();
 
-        for stream in listener.incoming() {
-            match stream {
-                Ok(stream) => {
-                    let request = Request::from(&stream);
+        println!("Starting event loop server on: {}", self.bind);
 
-                    let uri_name = request.get_raw_uri();
-                    let method_name_str: String = request.get_method().into();
+        let pool = ThreadPool::with_stats(settings.pool.clone(), self.pool_stats.clone());
 
-                    println!("Request returned: \"{}\"", &uri_name);
+        let mut event_loop = EventLoop::bind(&self.bind, dispatcher, pool).unwrap();
 
-                    let mut uri_split = uri_name.split("/").collect::<Vec<&str>>();
+        if let Err(err) = event_loop.run() {
+            panic!("{}", err)
+        }
+    }
 
-                    if uri_split.len() > 1 {
-                        if uri_split.last().unwrap().split(".").count() > 1 {
-                            uri_split.pop();
-                        }
-                    }
-                    let uri_fin = uri_split.join("/");
-
-                    let mutex_stream = std::sync::Mutex::new(stream);
-                    let arc_request = std::sync::Arc::new(request);
-                    println!("Looking for paths...");
-                    if self.bare_paths.contains(&uri_fin) {
-                        let uri_key = format!("{}_{}", uri_fin, method_name_str);
-                        println!("Serving...");
-                        match self.endpoints.get(&uri_key) {
-                            Some(endpoint) => endpoint.serve_response(mutex_stream, arc_request),
-                            None => {
-                                let ep_405 = self.endpoints.get(&"405_GET".to_string()).unwrap();
-
-                                ep_405.serve_response(mutex_stream, arc_request)
-                            }
+    fn run_threaded(&self) {
+        let settings = self.dispatcher.get_settings();
+
+        println!("Starting server on: {}", self.bind);
+
+        let listener = TcpListener::bind(&self.bind).unwrap();
+
+        let pool = ThreadPool::with_stats(settings.pool.clone(), self.pool_stats.clone());
+
+        for stream in listener.incoming() {
+            match stream {
+                Ok(mut stream) => {
+                    let slot = match pool.reserve() {
+                        Some(slot) => slot,
+                        None => {
+                            println!("Worker queue is full, shedding request");
+
+                            Endpoint::service_unavailable(settings.pool.get_retry_after())
+                                .compose()
+                                .serve(&mut stream);
+
+                            continue;
                         }
-                    } else {
-                        println!("Request 404'd");
-                        let ep_404 = self.endpoints.get(&"404_GET".to_string()).unwrap();
+                    };
+
+                    let dispatcher = self.dispatcher.clone();
 
-                        ep_404.serve_response(mutex_stream, arc_request)
-                    }
+                    slot.execute(move || Self::handle_connection(stream, &dispatcher));
                 }
                 Err(err) => panic!("{}", err),
             }
         }
     }
+
+    fn handle_connection(mut stream: TcpStream, dispatcher: &Dispatcher) {
+        let settings = dispatcher.get_settings();
+
+        let request = panic::catch_unwind(AssertUnwindSafe(|| {
+            Request::from_limited_timeout(&stream, settings.max_body_size, settings.read_timeout)
+        }))
+        .unwrap_or_else(|_| Err(ParseError::Malformed("unreadable request".to_string())));
+
+        let request = match request {
+            Ok(request) => request,
+            Err(e) => {
+                println!("Request could not be parsed");
+                e.into_response().compose().serve(&mut stream);
+                return;
+            }
+        };
+
+        let dispatched = panic::catch_unwind(AssertUnwindSafe(|| dispatcher.dispatch(request)));
+
+        let handled = match dispatched {
+            #[cfg(feature = "async")]
+            Ok(Dispatch::Endpoint(endpoint, request)) if endpoint.is_async() => {
+                endpoint.spawn_async(*request, settings, move |mut resp| {
+                    resp.compose().serve(&mut stream)
+                });
+
+                return;
+            }
+            Ok(Dispatch::Endpoint(endpoint, mut request)) => {
+                panic::catch_unwind(AssertUnwindSafe(|| {
+                    endpoint.respond(&mut request, &settings)
+                }))
+            }
+            Ok(Dispatch::Respond(resp)) => Ok(resp),
+            Err(e) => Err(e),
+        };
+
+        let mut resp = match handled {
+            Ok(resp) => resp,
+            Err(_) => HttpStatus::Http500InternalServerError.into_response(),
+        };
+
+        resp.compose().serve(&mut stream);
+    }
+}
+
+pub enum Dispatch {
+    Endpoint(Arc<Endpoint>, Box<Request>),
+    Respond(Response),
+}
+
+#[derive(Clone)]
+pub struct Dispatcher {
+    routes: Arc<RwLock<Arc<Router>>>,
+    state: Arc<StateMap>,
+    settings: Arc<Settings>,
+    ep_404: Arc<Endpoint>,
+    ep_405: Arc<Endpoint>,
+}
+
+impl Dispatcher {
+    pub fn get_settings(&self) -> Arc<Settings> {
+        self.settings.clone()
+    }
+
+    fn respond(&self, request: &Request, mut resp: Response) -> Dispatch {
+        let chain = self
+            .settings
+            .middleware
+            .iter()
+            .collect::<Vec<&MiddlewareRef>>();
+
+        middleware::run_after(&chain, request, &mut resp);
+
+        Dispatch::Respond(resp)
+    }
+
+    pub fn dispatch(&self, mut request: Request) -> Dispatch {
+        let routes = self.routes.read().unwrap().clone();
+        let settings = &self.settings;
+
+        request.set_url_table(routes.get_url_table());
+        request.set_state(self.state.clone());
+
+        let uri_name = request.get_raw_uri();
+
+        println!("Request returned: \"{}\"", uri_name);
+
+        let normalization = &settings.normalization;
+
+        let route = match normalization.normalize(&uri_name) {
+            Some(normalized) => {
+                let route = match normalization.get_trailing_slash() {
+                    TrailingSlash::Strict => routes.lookup_request_exact(
+                        &request,
+                        &normalized.path,
+                        settings.strict_hosts,
+                        normalized.trailing_slash,
+                    ),
+                    _ => routes.lookup_request(&request, &normalized.path, settings.strict_hosts),
+                };
+
+                let redirect = normalized.trailing_slash
+                    && normalization.get_trailing_slash() == TrailingSlash::Redirect
+                    && matches!(
+                        route,
+                        RouteMatch::Found(..) | RouteMatch::MethodNotAllowed(_)
+                    );
+
+                if redirect {
+                    let location = normalized.get_redirect(&request.get_location());
+
+                    println!("Redirecting to {}", location);
+
+                    return self.respond(&request, Redirect::permanent(&location).into_response());
+                }
+
+                request.set_uri(normalized.path);
+
+                route
+            }
+            None => RouteMatch::NotFound,
+        };
+
+        match route {
+            RouteMatch::Found(endpoint, params) => {
+                println!("Serving...");
+                request.set_path_params(params);
+
+                Dispatch::Endpoint(endpoint, Box::new(request))
+            }
+            RouteMatch::MethodNotAllowed(allowed) => {
+                let mut allowed = allowed
+                    .into_iter()
+                    .map(|m| m.into())
+                    .collect::<Vec<String>>();
+
+                allowed.sort();
+
+                let allow = allowed.join(", ");
+
+                let mut resp = self.ep_405.respond(&mut request, settings);
+                resp.set_header("Allow".to_string(), allow);
+
+                Dispatch::Respond(resp)
+            }
+            RouteMatch::UnsupportedMediaType => self.respond(
+                &request,
+                HttpStatus::Http415UnsupportedMediaType.into_response(),
+            ),
+            RouteMatch::Misdirected => {
+                println!("Request misdirected to {}", request.get_host());
+
+                self.respond(
+                    &request,
+                    HttpStatus::Http421MisdirectedRequest.into_response(),
+                )
+            }
+            RouteMatch::NotFound => {
+                println!("Request 404'd");
+
+                Dispatch::Endpoint(self.ep_404.clone(), Box::new(request))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::middleware::After;
+
+    #[test]
+    fn startup_only_settings_fail_once_started() {
+        let mut samovar = Samovar::new("127.0.0.1", 0);
+
+        assert!(samovar.insert_state(1u8).is_ok());
+        assert!(samovar
+            .add_middleware(After(|_: &Request, _: &mut Response| {}))
+            .is_ok());
+
+        let _server = samovar.server();
+
+        assert!(samovar.is_started());
+        assert!(samovar.insert_state(2u8).is_err());
+        assert!(samovar
+            .add_middleware(After(|_: &Request, _: &mut Response| {}))
+            .is_err());
+        assert_eq!(*samovar.get_state::<u8>().unwrap(), 1);
+    }
+
+    fn dispatched(dispatcher: &Dispatcher, headers: &[&str], location: &str) -> Response {
+        let request = crate::parser::test_request("GET", location, headers, "");
+
+        match dispatcher.dispatch(request) {
+            Dispatch::Endpoint(endpoint, mut request) => {
+                endpoint.respond(&mut request, &dispatcher.get_settings())
+            }
+            Dispatch::Respond(resp) => resp,
+        }
+    }
+
+    #[test]
+    fn dispatcher_responses_pass_through_global_middleware() {
+        let mut samovar = Samovar::new("127.0.0.1", 0);
+
+        samovar.set_strict_hosts(true);
+        samovar.set_path_normalization(
+            PathNormalization::new().trailing_slash(TrailingSlash::Redirect),
+        );
+        samovar
+            .add_middleware(After(|_: &Request, resp: &mut Response| {
+                resp.set_header("X-Global".to_string(), "yes".to_string())
+            }))
+            .unwrap();
+        samovar
+            .add_endpoint(
+                Endpoint::new("/a".to_string(), |_: &Request| "a", Method::GET)
+                    .with_host(crate::router::HostPattern::parse("h.test"))
+                    .with_guard(crate::guard::Guard::content_type(MimeType::ApplicationJson)),
+            )
+            .unwrap();
+
+        let dispatcher = samovar.dispatcher();
+        let json = "Content-Type: application/json";
+
+        let cases = [
+            (vec!["Host: h.test", json], "/a", "200"),
+            (vec!["Host: h.test", json], "/a/", "308"),
+            (
+                vec!["Host: h.test", "Content-Type: text/plain"],
+                "/a",
+                "415",
+            ),
+            (vec!["Host: other.test", json], "/a", "421"),
+            (vec!["Host: h.test", json], "/missing", "404"),
+        ];
+
+        for (headers, location, status) in cases {
+            let resp = dispatched(&dispatcher, &headers, location);
+            let status_line: String = resp.get_status().into();
+
+            assert!(status_line.starts_with(status), "{}", status_line);
+            assert_eq!(resp.get_header("X-Global").as_deref(), Some("yes"));
+        }
+    }
+
+    #[cfg(feature = "async")]
+    fn slow_hello(_: &Request) -> crate::endpoint::BoxFuture<'_, Response> {
+        Box::pin(async {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+
+            "hello".into_response()
+        })
+    }
+
+    #[cfg(feature = "async")]
+    fn serve_async_handler(engine: Engine) -> String {
+        use std::io::{Read, Write};
+        use std::thread;
+
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+
+        let mut samovar = Samovar::new("127.0.0.1", port as u32);
+        samovar.set_engine(engine);
+        samovar
+            .add_endpoint(Endpoint::new_async(
+                "/hello".to_string(),
+                slow_hello,
+                Method::GET,
+            ))
+            .unwrap();
+
+        thread::spawn(move || samovar.run());
+
+        let mut stream = (0..200)
+            .find_map(|_| {
+                TcpStream::connect(("127.0.0.1", port))
+                    .map_err(|_| thread::sleep(Duration::from_millis(10)))
+                    .ok()
+            })
+            .unwrap();
+
+        let mut response = String::new();
+
+        stream.write_all(b"GET /hello HTTP/1.1\r\n\r\n").unwrap();
+        stream.read_to_string(&mut response).ok();
+
+        response
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn serves_async_handlers_on_the_threaded_engine() {
+        let response = serve_async_handler(Engine::Threaded);
+
+        assert!(response.starts_with("HTTP/1.1 200"));
+        assert!(response.ends_with("hello"));
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn serves_async_handlers_on_the_event_loop_engine() {
+        let response = serve_async_handler(Engine::EventLoop);
+
+        assert!(response.starts_with("HTTP/1.1 200"));
+        assert!(response.ends_with("hello"));
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn serves_async_handlers_on_the_async_engine() {
+        let response = serve_async_handler(Engine::Async);
+
+        assert!(response.starts_with("HTTP/1.1 200"));
+        assert!(response.ends_with("hello"));
+    }
 }
@@ -0,0 +1,134 @@
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+#[derive(Clone, Default)]
+pub struct StateMap {
+    values: HashMap<TypeId, Arc<dyn Any + Send + Sync>>,
+}
+
+impl StateMap {
+    pub fn new() -> Self {
+        StateMap::default()
+    }
+
+    pub fn insert<T: Send + Sync + 'static>(&mut self, value: T) {
+        self.values.insert(TypeId::of::<T>(), Arc::new(value));
+    }
+
+    pub fn get<T: Send + Sync + 'static>(&self) -> Option<Arc<T>> {
+        self.values
+            .get(&TypeId::of::<T>())
+            .cloned()
+            .and_then(|value| value.downcast::<T>().ok())
+    }
+
+    pub fn remove<T: Send + Sync + 'static>(&mut self) -> Option<Arc<T>> {
+        self.values
+            .remove(&TypeId::of::<T>())
+            .and_then(|value| value.downcast::<T>().ok())
+    }
+
+    pub fn merge(&mut self, other: &StateMap) {
+        for (key, value) in other.values.iter() {
+            self.values.entry(*key).or_insert_with(|| value.clone());
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    pub fn contains<T: Send + Sync + 'static>(&self) -> bool {
+        self.values.contains_key(&TypeId::of::<T>())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::Method;
+    use crate::endpoint::Endpoint;
+    use crate::parser::test_request;
+    use crate::request::Request;
+    use crate::samovar::{Dispatch, Samovar};
+
+    struct Config {
+        name: &'static str,
+    }
+
+    #[test]
+    fn stores_one_value_per_type() {
+        let mut state = StateMap::new();
+
+        state.insert(Config { name: "a" });
+        state.insert(3u8);
+
+        assert_eq!(state.get::<Config>().unwrap().name, "a");
+        assert_eq!(*state.get::<u8>().unwrap(), 3);
+        assert_eq!(state.len(), 2);
+
+        state.insert(Config { name: "b" });
+
+        assert_eq!(state.get::<Config>().unwrap().name, "b");
+        assert_eq!(state.len(), 2);
+    }
+
+    #[test]
+    fn missing_types_are_none() {
+        let mut state = StateMap::new();
+
+        state.insert(1u32);
+
+        assert!(state.get::<u64>().is_none());
+        assert!(!state.contains::<u64>());
+        assert_eq!(*state.remove::<u32>().unwrap(), 1);
+        assert!(state.is_empty());
+    }
+
+    #[test]
+    fn merge_keeps_existing_values() {
+        let mut state = StateMap::new();
+        let mut other = StateMap::new();
+
+        state.insert(1u32);
+        other.insert(2u32);
+        other.insert(Config { name: "other" });
+
+        state.merge(&other);
+
+        assert_eq!(*state.get::<u32>().unwrap(), 1);
+        assert_eq!(state.get::<Config>().unwrap().name, "other");
+    }
+
+    #[test]
+    fn servers_hand_their_state_to_requests() {
+        let mut samovar = Samovar::new("127.0.0.1", 0);
+
+        samovar.insert_state(Config { name: "tea" }).unwrap();
+        samovar
+            .add_endpoint(Endpoint::new(
+                "/".to_string(),
+                |r: &Request| r.get_state::<Config>().unwrap().name,
+                Method::GET,
+            ))
+            .unwrap();
+
+        let _server = samovar.server();
+        let dispatcher = samovar.dispatcher();
+
+        assert!(samovar.insert_state(2u8).is_err());
+        assert!(samovar.get_state::<u8>().is_none());
+
+        match dispatcher.dispatch(test_request("GET", "/", &[], "")) {
+            Dispatch::Endpoint(_, request) => {
+                assert_eq!(request.get_state::<Config>().unwrap().name, "tea");
+            }
+            Dispatch::Respond(_) => panic!("expected a route"),
+        }
+    }
+}
@@ -1,10 +1,12 @@
 use crate::common::*;
+use crate::conditional::{ETag, ETagKind};
 use crate::response::Response;
+use chrono::{DateTime, Utc};
 use content_inspector::inspect;
 use glob::glob;
 use std::collections::HashMap;
-use std::fs::read_to_string;
-use std::path::PathBuf;
+use std::fs::{metadata, read};
+use std::path::{Path, PathBuf};
 
 lazy_static! {
     static ref HTML_SERVER: &'static str = r#"<html data-theme="coffee"><head><title>Chuby-HTTP FileServer --- Powered by Ritalin</title><link rel="preconnect" href="https://fonts.googleapis.com"><link rel="preconnect" href="https://fonts.gstatic.com" crossorigin><link href="https://fonts.googleapis.com/css2?family=Roboto:wght@300&display=swap" rel="stylesheet"><link href="https://cdn.jsdelivr.net/npm/daisyui@2.14.3/dist/full.css" rel="stylesheet" type="text/css"/><script src="https://cdn.tailwindcss.com"></script><link rel="stylesheet" href="https://cdnjs.cloudflare.com/ajax/libs/tailwindcss/2.2.19/tailwind.min.css" integrity="sha512-wnea99uKIC3TJF7v4eKk4Y+lMz2Mklv18+r4na2Gn1abDRPPOeef95xTzdwGD9e6zXJBteMIhZ1+68QC5byJZw==" crossorigin="anonymous" referrerpolicy="no-referrer"/></head><body><div class="flex flex-col place-items-center m-5 border-opacity-50">REPLACE_ALL</div><div class="grid h-20 w-full m-3 card bg-base-200 rounded-box place-items-center footer"><div class="footer-center">Powered by Samovar<br>Find me on Github: <a href="https://github.com/chbuek">github.com/chubek</a></div></div></html>"#;
@@ -27,8 +29,26 @@ lazy_static! {
 struct FileCache {
     path: PathBuf,
     mimetype: MimeType,
-    content: String,
+    content: Vec<u8>,
     uri: String,
+    etag: ETag,
+    modified: DateTime<Utc>,
+}
+
+impl FileCache {
+    fn respond(&self) -> Response {
+        let mut response = Response::new_bytes(
+            self.content.clone(),
+            self.mimetype.clone(),
+            HttpStatus::Http200Ok,
+        );
+
+        response.set_etag(self.etag.clone());
+        response.set_last_modified(self.modified);
+        response.enable_ranges();
+
+        response
+    }
 }
 
 #[derive(Clone)]
@@ -61,17 +81,21 @@ impl DirServer {
                 Ok(path) => {
                     println!("Found file {}", path.display());
 
-                    let content = read_to_string(path.clone()).unwrap();
-                    let is_text = inspect(content.clone().as_bytes()).is_text();
+                    let content = read(path.clone()).unwrap();
+                    let is_text = inspect(&content).is_text();
 
                     let mimetype = Self::guess_mime(path.clone(), is_text.clone());
                     let uri = Self::get_uri(path_fin.clone(), path.clone());
                     println!("Got file URI: {}", &uri);
+                    let etag = ETag::from_bytes(&content, ETagKind::Strong);
+                    let modified = Self::get_modified(&path);
                     let fc = FileCache {
                         path,
                         mimetype,
                         content,
                         uri,
+                        etag,
+                        modified,
                     };
 
                     cache.push(fc);
@@ -93,6 +117,13 @@ impl DirServer {
         self.path_uri.clone().to_owned()
     }
 
+    fn get_modified(path: &Path) -> DateTime<Utc> {
+        match metadata(path).and_then(|m| m.modified()) {
+            Ok(modified) => DateTime::<Utc>::from(modified),
+            Err(_) => Utc::now(),
+        }
+    }
+
     fn guess_mime(path: PathBuf, is_text: bool) -> MimeType {
         match path.extension() {
             Some(ext) => {
@@ -102,6 +133,10 @@ impl DirServer {
                         "css" => MimeType::TextCSS,
                         "json" => MimeType::ApplicationJson,
                         "html" => MimeType::TextHtml,
+                        "png" => MimeType::ImagePng,
+                        "jpg" | "jpeg" => MimeType::ImageJpeg,
+                        "gif" => MimeType::ImageGif,
+                        "pdf" => MimeType::ApplicationPdf,
                         _ => match is_text {
                             true => MimeType::TextPlain,
                             false => MimeType::ApplicationOctetStream,
@@ -149,7 +184,7 @@ impl DirServer {
         let copy_str = HTML_FILE.clone().to_string();
 
         let fname = item.path.file_name().unwrap().to_str().unwrap();
-        let badge_color = BADGE_MAP[&item.mimetype];
+        let badge_color = BADGE_MAP.get(&item.mimetype).unwrap_or(&"badge-accent");
         let ftype: String = item.mimetype.clone().into();
 
         let mut replaced = copy_str.replace("REPLACE_HREF", &item.uri);
@@ -174,50 +209,25 @@ impl DirServer {
         replace
     }
 
-    fn crease_response_with_file(&self, uri: String) -> ResponseTextWrapper {
-        let mut cache_clone = self.cache.clone();
-
-        cache_clone.retain(|x| x.uri == uri);
-
-        let item = &cache_clone[0];
-
-        let mut response = Response::<DummyResponseType>::new_string(
-            item.content.clone(),
-            item.mimetype.clone(),
-            HttpStatus::Http200Ok,
-        );
-
-        let response_text = response.compose();
-
-        response_text
+    fn crease_response_with_file(&self, uri: String) -> Response {
+        match self.cache.iter().find(|x| x.uri.eq_ignore_ascii_case(&uri)) {
+            Some(item) => item.respond(),
+            None => Response::new_string(
+                "No such file".to_string(),
+                MimeType::TextPlain,
+                HttpStatus::Http404NotFound,
+            ),
+        }
     }
 
-    fn create_response_with_index(&self) -> ResponseTextWrapper {
+    fn create_response_with_index(&self) -> Response {
         let list = self.create_file_list();
 
-        let mut response = Response::<DummyResponseType>::new_string(
-            list,
-            MimeType::TextHtml,
-            HttpStatus::Http200Ok,
-        );
-
-        let response_text = response.compose();
-
-        response_text
+        Response::new_string(list, MimeType::TextHtml, HttpStatus::Http200Ok)
     }
 
-    fn create_response_with_index_file(&self) -> ResponseTextWrapper {
-        let fc_index = self.get_index_file();
-
-        let fc_str = fc_index.content.clone();
-        let fc_type = fc_index.mimetype.clone();
-
-        let mut response =
-            Response::<DummyResponseType>::new_string(fc_str, fc_type, HttpStatus::Http200Ok);
-
-        let response_text = response.compose();
-
-        response_text
+    fn create_response_with_index_file(&self) -> Response {
+        self.get_index_file().respond()
     }
 
     fn get_index_file(&self) -> &FileCache {
@@ -248,17 +258,19 @@ impl DirServer {
         is_in
     }
 
-    pub fn respond_empty(&self) -> ResponseTextWrapper {
-        let mut resp = Response::<DummyResponseType>::new_string(
+    pub fn respond_empty(&self) -> Response {
+        Response::new_string(
             "No files in this glob".to_string(),
             MimeType::TextPlain,
             HttpStatus::Http404NotFound,
-        );
-
-        resp.compose()
+        )
     }
 
     pub fn compose(&self, uri: String) -> ResponseTextWrapper {
+        self.respond(uri).compose()
+    }
+
+    pub fn respond(&self, uri: String) -> Response {
         println!("Serving static file on uri {}", &uri);
 
         if self.cache.len() == 0 {
@@ -266,7 +278,7 @@ impl DirServer {
             return self.respond_empty();
         }
 
-        match uri == self.path_uri {
+        match uri.eq_ignore_ascii_case(&self.path_uri) {
             true => match self.index_file {
                 Some(_) => self.create_response_with_index_file(),
                 None => {
@@ -283,33 +295,33 @@ pub struct SingleFileServer(FileCache);
 
 impl SingleFileServer {
     pub fn new(path: PathBuf, path_uri: String) -> Self {
-        let content = read_to_string(path.clone()).expect("File does not exist");
-        let is_text = inspect(content.as_bytes()).is_text();
+        let content = read(path.clone()).expect("File does not exist");
+        let is_text = inspect(&content).is_text();
         let mimetype = DirServer::guess_mime(path.clone(), is_text);
         let uri = DirServer::get_uri(path_uri, path.clone());
+        let etag = ETag::from_bytes(&content, ETagKind::Strong);
+        let modified = DirServer::get_modified(&path);
 
         let fc = FileCache {
             content,
             mimetype,
             uri,
             path,
+            etag,
+            modified,
         };
 
         SingleFileServer(fc)
     }
 
-    fn compose(&self) -> ResponseTextWrapper {
+    pub fn respond(&self) -> Response {
         let SingleFileServer(fc) = self;
 
-        let mut response = Response::<DummyResponseType>::new_string(
-            fc.content.clone(),
-            fc.mimetype.clone(),
-            HttpStatus::Http200Ok,
-        );
-
-        let response_text = response.compose();
+        fc.respond()
+    }
 
-        response_text
+    fn compose(&self) -> ResponseTextWrapper {
+        self.respond().compose()
     }
 }
 